@@ -0,0 +1,195 @@
+//! Throughput and memory benchmarks of the processing engine on generated
+//! workloads
+//!
+//! Run with `cargo bench`. Throughput is reported in transactions per second,
+//! and the memory used per million transactions is written out before the
+//! timings
+
+use transaction_processor::generate::{write_workload,Generator,WorkloadConfig};
+use transaction_processor::parse::Columns;
+use transaction_processor::processor::TransactionProcessor;
+use transaction_processor::transaction::Transaction;
+use transaction_processor::types::*;
+
+use std::alloc::{GlobalAlloc,Layout,System};
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+use criterion::{criterion_group,criterion_main,BatchSize,BenchmarkId,Criterion,Throughput};
+use csv::{ByteRecord,ReaderBuilder,Trim};
+
+/// Numbers of transactions to benchmark with
+const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+/// Allocator keeping track of the memory in use and its peak
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Get a generated workload of a number of transactions
+///
+/// Note: Transactions are spread across every Client ID so that chargebacks
+/// don't lock most accounts in the larger workloads
+fn workload(rows: usize) -> WorkloadConfig {
+    WorkloadConfig { clients: ClientID::MAX, rows, seed: 1, ..WorkloadConfig::default() }
+}
+
+/// Get a generated workload as CSV
+fn csv_input(rows: usize) -> Vec<u8> {
+    let mut input = Vec::new();
+    write_workload(&workload(rows), &mut input).unwrap();
+    input
+}
+
+/// Get a generated workload as transactions
+fn transactions(rows: usize) -> Vec<Transaction> {
+    Generator::new(workload(rows)).map(|row| row.to_transaction()).collect()
+}
+
+/// Write out the memory held by a TransactionProcessor after processing each
+/// workload, and the peak while processing it
+fn memory() {
+    for rows in SIZES {
+        let input = csv_input(rows);
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(before, Ordering::Relaxed);
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_slice()).unwrap();
+        let held = ALLOCATED.load(Ordering::Relaxed) - before;
+        let peak = PEAK.load(Ordering::Relaxed) - before;
+        drop(tp);
+
+        let per_million = |bytes: usize| bytes as f64 / (1 << 20) as f64 * 1e6 / rows as f64;
+        println!("memory/{}: {:.1} MiB held, {:.1} MiB peak per million transactions",
+                 rows, per_million(held), per_million(peak));
+    }
+}
+
+fn process_csv_stream(c: &mut Criterion) {
+    memory();
+
+    let mut group = c.benchmark_group("process_csv_stream");
+    group.sample_size(10);
+    for rows in SIZES {
+        let input = csv_input(rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        // the fast path is the default, serde is kept for comparison
+        for (name, fast) in [("fast", true), ("serde", false)] {
+            group.bench_with_input(BenchmarkId::new(name, rows), &input, |b, input| {
+                b.iter(|| {
+                    let mut tp = TransactionProcessor::new();
+                    tp.set_fast_csv(fast);
+                    tp.process_csv_stream(input.as_slice()).unwrap();
+                    tp
+                })
+            });
+        }
+
+        // memory mapped and parsed in parallel
+        let path = env::temp_dir().join(format!("bench_{}_{}.csv", process::id(), rows));
+        fs::write(&path, &input).unwrap();
+        group.bench_with_input(BenchmarkId::new("mapped", rows), &path, |b, path| {
+            b.iter(|| {
+                let mut tp = TransactionProcessor::new();
+                tp.process_csv_file(path).unwrap();
+                tp
+            })
+        });
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
+fn process_transaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_transaction");
+    group.sample_size(10);
+    for rows in SIZES {
+        let transactions = transactions(rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_function(BenchmarkId::from_parameter(rows), |b| {
+            b.iter_batched(|| transactions.clone(), |transactions| {
+                let mut tp = TransactionProcessor::new();
+                for trans in transactions {
+                    tp.process_transaction(trans);
+                }
+                tp
+            }, BatchSize::PerIteration)
+        });
+    }
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    group.sample_size(10);
+    for rows in SIZES {
+        let input = csv_input(rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::new("fast", rows), &input, |b, input| {
+            b.iter(|| {
+                let mut csv_reader = ReaderBuilder::new()
+                    .trim(Trim::Headers)
+                    .from_reader(input.as_slice());
+                let columns = Columns::new(csv_reader.headers().unwrap()).unwrap();
+                let mut record = ByteRecord::new();
+                let mut last = None;
+                while csv_reader.read_byte_record(&mut record).unwrap() {
+                    last = columns.parse(&record);
+                }
+                last
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("serde", rows), &input, |b, input| {
+            b.iter(|| {
+                let mut csv_reader = ReaderBuilder::new()
+                    .trim(Trim::All)
+                    .from_reader(input.as_slice());
+                csv_reader.deserialize::<Transaction>().map(|t| t.unwrap()).last()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn write_csv_to_stream(c: &mut Criterion) {
+    let clients = ClientID::MAX as usize;
+    let mut tp = TransactionProcessor::new();
+    for id in 1..=ClientID::MAX {
+        tp.process_transaction(Transaction::new_deposit(id, id as TransactionID, 100.0, false));
+    }
+
+    let mut group = c.benchmark_group("write_csv_to_stream");
+    group.throughput(Throughput::Elements(clients as u64));
+    group.bench_function(BenchmarkId::from_parameter(clients), |b| {
+        b.iter(|| tp.write_csv_to_stream(io::sink()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, process_csv_stream, process_transaction, deserialize,
+                 write_csv_to_stream);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transaction_processor::config::Config;
+use transaction_processor::processor::TransactionProcessor;
+
+use std::io;
+
+/// Reorder late transactions and expire disputes so those paths are covered
+const CONFIG: &str = "[timestamps]\n\
+                      reorder_tolerance = 5.0\n\
+                      [policy]\n\
+                      dispute_window = 60.0\n\
+                      dispute_deadline = 30.0\n";
+
+/// Create a TransactionProcessor with or without the fast CSV path
+fn processor(fast_csv: bool) -> TransactionProcessor {
+    let mut tp = TransactionProcessor::new();
+    tp.set_config(Config::from_toml(CONFIG).unwrap());
+    tp.set_allow_admin(true);
+    tp.set_checked(true);
+    tp.set_fast_csv(fast_csv);
+    tp
+}
+
+// Arbitrary bytes may be rejected as malformed but must never panic, the
+//  account invariants must hold after every transaction that's processed and
+//  the fast CSV path must give the same result as serde
+fuzz_target!(|data: &[u8]| {
+    let mut tp = processor(true);
+    let result = tp.process_csv_stream(data).map_err(|e| e.to_string());
+    tp.write_csv_to_stream(io::sink()).unwrap();
+
+    let mut serde = processor(false);
+    let serde_result = serde.process_csv_stream(data).map_err(|e| e.to_string());
+    assert_eq!(result, serde_result);
+    // compared as text since NaN balances never compare equal
+    assert_eq!(format!("{:?}", tp.get_records()), format!("{:?}", serde.get_records()));
+});
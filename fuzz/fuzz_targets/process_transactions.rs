@@ -0,0 +1,110 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use transaction_processor::config::Config;
+use transaction_processor::policy::NegativeBalanceMode;
+use transaction_processor::processor::TransactionProcessor;
+use transaction_processor::transaction::{Transaction,TransactionType};
+use transaction_processor::types::*;
+
+use std::io;
+
+/// Every transaction type, for admin operations built with any type
+const TYPES: [TransactionType; 14] = {
+    use TransactionType::*;
+    [Deposit, Withdrawal, Transfer, Dispute, Resolve, Chargeback, Authorize,
+     Capture, Void, Unlock, Freeze, Close, Adjust, Limit]
+};
+
+/// A transaction built with one of the Transaction constructors
+///
+/// Client and transaction IDs are kept small so transactions refer to each
+/// other often
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Deposit { client: u8, tx: u8, amount: f64 },
+    Withdrawal { client: u8, tx: u8, amount: f64 },
+    Transfer { client: u8, counterparty: u8, tx: u8, amount: f64 },
+    Dispute { client: u8, tx: u8, amount: Option<f64> },
+    Resolve { client: u8, tx: u8 },
+    Chargeback { client: u8, tx: u8, amount: Option<f64> },
+    Authorize { client: u8, tx: u8, amount: f64 },
+    Capture { client: u8, tx: u8, amount: Option<f64> },
+    Void { client: u8, tx: u8 },
+    Admin { typ: u8, client: u8, tx: u8, amount: Option<f64>, reason: Option<String> }
+}
+
+impl Op {
+    fn to_transaction(&self) -> Transaction {
+        let c = |client: &u8| *client as ClientID % 4;
+        let t = |tx: &u8| *tx as TransactionID % 16;
+        match self {
+            Op::Deposit { client, tx, amount } => {
+                Transaction::new_deposit(c(client), t(tx), *amount, false)
+            },
+            Op::Withdrawal { client, tx, amount } => {
+                Transaction::new_withdrawl(c(client), t(tx), *amount, false)
+            },
+            Op::Transfer { client, counterparty, tx, amount } => {
+                Transaction::new_transfer(c(client), c(counterparty), t(tx), *amount,
+                                          false)
+            },
+            Op::Dispute { client, tx, amount: None } => {
+                Transaction::new_dispute(c(client), t(tx))
+            },
+            Op::Dispute { client, tx, amount: Some(amount) } => {
+                Transaction::new_partial_dispute(c(client), t(tx), *amount)
+            },
+            Op::Resolve { client, tx } => Transaction::new_resolve(c(client), t(tx)),
+            Op::Chargeback { client, tx, amount: None } => {
+                Transaction::new_chargeback(c(client), t(tx))
+            },
+            Op::Chargeback { client, tx, amount: Some(amount) } => {
+                Transaction::new_partial_chargeback(c(client), t(tx), *amount)
+            },
+            Op::Authorize { client, tx, amount } => {
+                Transaction::new_authorize(c(client), t(tx), *amount)
+            },
+            Op::Capture { client, tx, amount } => {
+                Transaction::new_capture(c(client), t(tx), *amount)
+            },
+            Op::Void { client, tx } => Transaction::new_void(c(client), t(tx)),
+            Op::Admin { typ, client, tx, amount, reason } => {
+                Transaction::new_admin(TYPES[*typ as usize % TYPES.len()], c(client),
+                                       t(tx), *amount, reason.as_deref())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    negative_balance: u8,
+    dispute_deadline: Option<u16>,
+    /// Transactions, each with an optional timestamp
+    ops: Vec<(Op, Option<Timestamp>)>
+}
+
+// Sequences of transactions must never panic, and the account invariants must
+//  hold after every transaction
+fuzz_target!(|input: Input| {
+    let mut config = Config::default();
+    config.policy.negative_balance = match input.negative_balance % 3 {
+        0 => NegativeBalanceMode::Allow,
+        1 => NegativeBalanceMode::Cap,
+        _ => NegativeBalanceMode::Reject
+    };
+    config.policy.dispute_deadline = input.dispute_deadline.map(f64::from);
+
+    let mut tp = TransactionProcessor::new();
+    tp.set_config(config);
+    tp.set_allow_admin(true);
+    tp.set_checked(true);
+    for (op, timestamp) in &input.ops {
+        let mut trans = op.to_transaction();
+        trans.set_timestamp(*timestamp);
+        tp.process_transaction(trans);
+    }
+    tp.write_csv_to_stream(io::sink()).unwrap();
+});
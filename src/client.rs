@@ -1,19 +1,64 @@
 use crate::types::*;
+use crate::history::{Changes,Event,RecentTransactions,RejectReason,TransactionInfo};
+use crate::status::AccountStatus;
+use crate::transaction::TransactionType;
 
-use serde::Serialize;
+use serde::{Deserialize,Serialize};
 
 /// Struct representing a Client's info
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
 pub struct Client {
-    #[serde(rename = "client")]
     id: ClientID,
-    #[serde(serialize_with = "serialize_f64_to_4")]
     available: f64,
-    #[serde(serialize_with = "serialize_f64_to_4")]
     held: f64,
-    #[serde(serialize_with = "serialize_f64_to_4")]
     total: f64,
-    locked: bool
+    status: AccountStatus,
+    chargebacks: u32,
+    disputes: u32,
+    last_timestamp: Option<Timestamp>,
+    receivable: f64,
+    overdraft_limit: f64,
+    tier: Option<String>,
+    recent: RecentTransactions,
+    history: Option<Vec<Event>>,
+    changes: Changes,
+    flags: Vec<String>
+}
+
+/// A Client's account state as written to CSV
+///
+/// Note: status, receivable and credit_used are left out of the legacy
+/// output format
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ClientRecord {
+    pub client: ClientID,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    pub available: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    pub held: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    pub total: f64,
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<AccountStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_opt_f64_to_4")]
+    pub receivable: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_opt_f64_to_4")]
+    pub credit_used: Option<f64>
+}
+
+impl ClientRecord {
+    /// Validate the account state
+    ///
+    /// Note: The balances are written to 4 decimal places so total may be off
+    /// by one in the last place from rounding
+    pub fn validate(&self) -> bool {
+        ((self.available + self.held - self.total).abs() < 0.00015) &&
+        self.status.is_none_or(|s| (s == AccountStatus::Locked) == self.locked) &&
+        self.receivable.is_none_or(|r| r >= 0.0)
+    }
 }
 
 impl Client {
@@ -24,7 +69,52 @@ impl Client {
             available: 0.0,
             held: 0.0,
             total: 0.0,
-            locked: false
+            status: AccountStatus::Active,
+            chargebacks: 0,
+            disputes: 0,
+            last_timestamp: None,
+            receivable: 0.0,
+            overdraft_limit: 0.0,
+            tier: None,
+            recent: RecentTransactions::default(),
+            history: None,
+            changes: Changes::default(),
+            flags: Vec::new()
+        }
+    }
+
+    /// Start recording an event history for a Client
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(Vec::new());
+        }
+    }
+
+    /// Stop recording an event history for a Client, discarding any recorded
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Get a Client's event history
+    ///
+    /// Note: The history is empty unless it was enabled with `enable_history`
+    pub fn get_history(&self) -> &[Event] {
+        self.history.as_deref().unwrap_or(&[])
+    }
+
+    /// Record the result of a transaction in a Client's history
+    ///
+    /// All balance changes made and flags raised since the last recorded
+    /// event are attributed to this transaction
+    pub fn record_event(&mut self, trans: &TransactionInfo,
+                        result: Result<(),RejectReason>) {
+        let changes = std::mem::take(&mut self.changes);
+        let flags = std::mem::take(&mut self.flags);
+        if self.history.is_some() {
+            let event = Event::new(self, trans, result, changes, &flags);
+            if let Some(history) = self.history.as_mut() {
+                history.push(event);
+            }
         }
     }
 
@@ -54,39 +144,177 @@ impl Client {
         self.total
     }
 
-    /// Get lock status of Client
+    /// Get the status of a Client's account
+    pub fn get_status(&self) -> AccountStatus {
+        self.status
+    }
+
+    /// Set the status of a Client's account
     ///
-    /// A client's balances can't be modified if the account is locked
+    /// Note: Which transactions are allowed in each status is decided by the
+    /// TransactionProcessor
+    #[allow(dead_code)]
+    pub fn set_status(&mut self, status: AccountStatus) {
+        self.status = status;
+    }
+
+    /// Get the number of chargebacks made against a Client's account
+    pub fn get_chargebacks(&self) -> u32 {
+        self.chargebacks
+    }
+
+    /// Count a chargeback made against a Client's account
+    pub fn add_chargeback(&mut self) {
+        self.chargebacks += 1;
+    }
+
+    /// Get the time of the latest transaction for a Client
+    pub fn get_last_timestamp(&self) -> Option<Timestamp> {
+        self.last_timestamp
+    }
+
+    /// Set the time of the latest transaction for a Client
+    pub fn set_last_timestamp(&mut self, timestamp: Timestamp) {
+        self.last_timestamp = Some(timestamp);
+    }
+
+    /// Get the number of disputes raised against a Client's account
+    pub fn get_disputes(&self) -> u32 {
+        self.disputes
+    }
+
+    /// Count a dispute raised against a Client's account
+    pub fn add_dispute(&mut self) {
+        self.disputes += 1;
+    }
+
+    /// Get the amount a Client owes for disputes that exceeded their
+    /// available funds
+    pub fn get_receivable(&self) -> f64 {
+        self.receivable
+    }
+
+    /// Record an amount a Client owes
+    pub fn add_receivable(&mut self, amount: f64) {
+        self.receivable += amount;
+    }
+
+    /// Clear an amount a Client owes
+    pub fn remove_receivable(&mut self, amount: f64) {
+        self.receivable -= amount;
+    }
+
+    /// Get how far a Client's available funds may go below 0
+    #[allow(dead_code)]
+    pub fn get_overdraft_limit(&self) -> f64 {
+        self.overdraft_limit
+    }
+
+    /// Set how far a Client's available funds may go below 0
+    pub fn set_overdraft_limit(&mut self, limit: f64) {
+        self.overdraft_limit = limit;
+    }
+
+    /// Get the funds a Client can spend, including their overdraft
+    pub fn get_spendable_funds(&self) -> f64 {
+        self.available + self.overdraft_limit
+    }
+
+    /// Get the part of a Client's overdraft that's in use
+    pub fn get_credit_used(&self) -> f64 {
+        if self.available < 0.0 {
+            (-self.available).min(self.overdraft_limit)
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the tier a Client's settings are taken from
+    pub fn get_tier(&self) -> Option<&str> {
+        self.tier.as_deref()
+    }
+
+    /// Set the tier a Client's settings are taken from
+    pub fn set_tier(&mut self, tier: Option<String>) {
+        self.tier = tier;
+    }
+
+    /// Get a Client's most recent applied transactions
+    pub fn get_recent(&self) -> &RecentTransactions {
+        &self.recent
+    }
+
+    /// Record an applied transaction in a Client's most recent transactions,
+    /// keeping at most size transactions along with any made within span
+    /// milliseconds of it
+    pub fn record_recent(&mut self, typ: TransactionType, amount: Option<f64>,
+                         timestamp: Option<Timestamp>, size: usize,
+                         span: Option<Timestamp>) {
+        self.recent.record(typ, amount, timestamp, size, span);
+    }
+
+    /// Flag the transaction being processed for a Client with the name of a
+    /// rule that matched it
+    ///
+    /// Note: The flag is recorded with the transaction's event
+    pub fn add_flag(&mut self, name: &str) {
+        self.flags.push(name.to_string());
+    }
+
+    /// Get lock status of Client
     #[allow(dead_code)]
     pub fn is_locked(&self) -> bool {
-        self.locked
+        self.status == AccountStatus::Locked
+    }
+
+    /// Get a Client's account state for output
+    ///
+    /// Note: The legacy format has no status, receivable or credit_used
+    pub fn to_record(&self, legacy: bool) -> ClientRecord {
+        ClientRecord {
+            client: self.id,
+            available: self.available,
+            held: self.held,
+            total: self.total,
+            locked: self.is_locked(),
+            status: if legacy { None } else { Some(self.status) },
+            receivable: if legacy { None } else { Some(self.receivable) },
+            credit_used: if legacy { None } else { Some(self.get_credit_used()) }
+        }
+    }
+
+    /// Set a Client's balances and status from a record of its account state
+    ///
+    /// Note: Records without a status are active unless locked
+    pub fn load_record(&mut self, record: &ClientRecord) {
+        self.available = record.available;
+        self.held = record.held;
+        self.total = record.total;
+        self.status = record.status.unwrap_or(if record.locked {
+            AccountStatus::Locked
+        } else {
+            AccountStatus::Active
+        });
+        self.receivable = record.receivable.unwrap_or(0.0);
     }
 
     /// Add funds to a Client's account
     ///
-    /// Operation will fail if Client's account is locked
+    /// Operation always succeeds
     pub fn add_funds(&mut self, amount: f64) -> bool {
-        // only add funds if account isn't locked
-        if !self.locked {
-            self.available += amount;
-            self.total += amount;
+        self.apply(amount, 0.0);
 
-            true
-        } else {
-            false
-        }
+        true
     }
 
     /// Remove funds from a Client's account
     ///
-    /// Operation will fail if Client's account is locked or there are not
-    /// sufficient available funds
+    /// Operation will fail if there are not sufficient available funds,
+    /// including the Client's overdraft
     pub fn remove_funds(&mut self, amount: f64) -> bool {
-        // only remove funds if account isn't locked and required
-        //  funds are available
-        if (self.available >= amount) && (!self.locked) {
-            self.available -= amount;
-            self.total -= amount;
+        // only remove funds if required funds are available
+        if self.get_spendable_funds() >= amount {
+            self.apply(-amount, 0.0);
 
             true
         } else {
@@ -96,12 +324,20 @@ impl Client {
 
     /// Hold funds in a Client's account
     ///
-    /// Operation will fail if Client's account is locked
+    /// Operation always succeeds
     pub fn hold_funds(&mut self, amount: f64) -> bool {
-        // only hold funds if account isn't locked
-        if !self.locked {
-            self.available -= amount;
-            self.held += amount;
+        self.apply(-amount, amount);
+
+        true
+    }
+
+    /// Restore held funds for a Client's account
+    ///
+    /// Operation will fail if there are not sufficient held funds
+    pub fn restore_funds(&mut self, amount: f64) -> bool {
+        // only restore funds if required held funds are available
+        if self.held >= amount {
+            self.apply(amount, -amount);
 
             true
         } else {
@@ -109,16 +345,13 @@ impl Client {
         }
     }
 
-    /// Restore held funds for a Client's account
+    /// Remove held funds from a Client's account
     ///
-    /// Operation will fail if Client's account is locked or there are not
-    /// sufficient held funds
-    pub fn restore_funds(&mut self, amount: f64) -> bool {
-        // only restore funds if account isn't locked and required held
-        //  funds are available
-        if (self.held >= amount) && (!self.locked) {
-            self.available += amount;
-            self.held -= amount;
+    /// Operation will fail if there are not sufficient held funds
+    pub fn remove_held_funds(&mut self, amount: f64) -> bool {
+        // only remove funds if required held funds are available
+        if self.held >= amount {
+            self.apply(0.0, -amount);
 
             true
         } else {
@@ -127,27 +360,64 @@ impl Client {
     }
 
     /// Lock a Client's account
-    ///
-    /// A client's balances can't be modified if the account is locked
     pub fn lock(&mut self) {
-        self.locked = true;
+        self.status = AccountStatus::Locked;
     }
 
     /// Unlock a Client's account
-    #[allow(dead_code)]
+    ///
+    /// This also lifts any freeze and reopens a closed or dormant account
     pub fn unlock(&mut self) {
-        self.locked = false;
+        self.status = AccountStatus::Active;
+    }
+
+    /// Freeze a Client's account
+    pub fn freeze(&mut self) {
+        self.status = AccountStatus::Frozen;
+    }
+
+    /// Close a Client's account
+    pub fn close(&mut self) {
+        self.status = AccountStatus::Closed;
+    }
+
+    /// Correct a Client's available funds by a signed amount
+    ///
+    /// Note: This may leave the available funds negative
+    pub fn adjust_funds(&mut self, amount: f64) {
+        self.apply(amount, 0.0);
+    }
+
+    /// Apply a change to a Client's balances and track it for the history
+    fn apply(&mut self, available: f64, held: f64) {
+        self.available += available;
+        self.held += held;
+        self.total += available + held;
+
+        self.changes.available += available;
+        self.changes.held += held;
     }
 }
 
-fn serialize_f64_to_4<S>(data: &f64, s: S) -> Result<S::Ok, S::Error>
+/// Serialize an f64 as a string with 4 decimal places
+pub fn serialize_f64_to_4<S>(data: &f64, s: S) -> Result<S::Ok, S::Error>
         where S: serde::Serializer {
     s.serialize_str(format!("{:.4}",data).as_str())
 }
 
+/// Serialize an optional f64 as a string with 4 decimal places
+pub fn serialize_opt_f64_to_4<S>(data: &Option<f64>, s: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer {
+    match data {
+        Some(data) => serialize_f64_to_4(data, s),
+        None => s.serialize_none()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::transaction::Transaction;
 
     #[test]
     fn add_funds() {
@@ -187,7 +457,7 @@ mod test {
         c.available = amount;
         c.total = amount;
 
-        assert_eq!(c.remove_funds(amount+1.0),false);
+        assert!(!c.remove_funds(amount+1.0));
         assert_eq!(c.available,amount);
         assert_eq!(c.total,amount);
     }
@@ -237,17 +507,18 @@ mod test {
         assert_eq!(c.get_available_funds(), c.available);
         assert_eq!(c.get_total_funds(), c.total);
         assert_eq!(c.get_client_id(), c.id);
-        assert_eq!(c.is_locked(), c.locked);
+        assert_eq!(c.is_locked(), c.status == AccountStatus::Locked);
+        assert_eq!(c.get_status(), c.status);
     }
 
     #[test]
     fn lock() {
         let mut c = Client::new(500);
 
-        assert!(!c.locked);
+        assert_eq!(c.status, AccountStatus::Active);
         assert!(!c.is_locked());
         c.lock();
-        assert!(c.locked);
+        assert_eq!(c.status, AccountStatus::Locked);
         assert!(c.is_locked());
     }
 
@@ -256,10 +527,161 @@ mod test {
         let mut c = Client::new(500);
 
         c.lock();
-        assert!(c.locked);
+        assert_eq!(c.status, AccountStatus::Locked);
         assert!(c.is_locked());
         c.unlock();
-        assert!(!c.locked);
+        assert_eq!(c.status, AccountStatus::Active);
         assert!(!c.is_locked());
     }
+
+    #[test]
+    fn record_event() {
+        let mut c = Client::new(500);
+        c.enable_history();
+
+        c.add_funds(100.0);
+        c.hold_funds(10.0);
+        let deposit = Transaction::new_deposit(500, 600, 100.0, false);
+        let withdrawal = Transaction::new_withdrawl(500, 601, 100.0, false);
+        c.record_event(&TransactionInfo::new(&deposit), Ok(()));
+        c.record_event(&TransactionInfo::new(&withdrawal),
+                       Err(RejectReason::InsufficientFunds));
+
+        let history = c.get_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].get_changes(),
+                   Changes { available: 90.0, held: 10.0 });
+        assert_eq!(history[1].get_changes(), Changes::default());
+    }
+
+    #[test]
+    fn record_event_disabled() {
+        let mut c = Client::new(500);
+
+        c.add_funds(100.0);
+        let deposit = Transaction::new_deposit(500, 600, 100.0, false);
+        c.record_event(&TransactionInfo::new(&deposit), Ok(()));
+
+        assert!(c.get_history().is_empty());
+        assert_eq!(c.changes, Changes::default());
+    }
+
+    #[test]
+    fn freeze_close() {
+        let mut c = Client::new(500);
+
+        c.freeze();
+        assert_eq!(c.get_status(), AccountStatus::Frozen);
+        assert!(!c.is_locked());
+        c.close();
+        assert_eq!(c.get_status(), AccountStatus::Closed);
+        c.unlock();
+        assert_eq!(c.get_status(), AccountStatus::Active);
+    }
+
+    #[test]
+    fn to_record() {
+        let mut c = Client::new(500);
+        c.add_funds(100.0);
+        c.lock();
+
+        let r = c.to_record(false);
+        assert_eq!(r.client, 500);
+        assert_eq!(r.available, 100.0);
+        assert!(r.locked);
+        assert_eq!(r.status, Some(AccountStatus::Locked));
+        assert_eq!(c.to_record(true).status, None);
+    }
+
+    #[test]
+    fn adjust_funds() {
+        let mut c = Client::new(500);
+        let amount = 100.0;
+
+        c.available = amount;
+        c.total = amount;
+
+        c.adjust_funds(-150.0);
+        assert_eq!(c.available,-50.0);
+        assert_eq!(c.total,-50.0);
+    }
+
+    #[test]
+    fn add_chargeback() {
+        let mut c = Client::new(500);
+
+        assert_eq!(c.get_chargebacks(), 0);
+        c.add_chargeback();
+        c.add_chargeback();
+        assert_eq!(c.get_chargebacks(), 2);
+        assert!(!c.is_locked());
+    }
+
+    #[test]
+    fn remove_held_funds() {
+        let mut c = Client::new(500);
+
+        c.available = -10.0;
+        c.held = 50.0;
+        c.total = 40.0;
+
+        assert!(c.remove_held_funds(50.0));
+        assert_eq!(c.held,0.0);
+        assert_eq!(c.available,-10.0);
+        assert_eq!(c.total,-10.0);
+        assert!(!c.remove_held_funds(1.0));
+    }
+
+    #[test]
+    fn receivable() {
+        let mut c = Client::new(500);
+
+        c.add_receivable(30.0);
+        c.remove_receivable(10.0);
+        assert_eq!(c.get_receivable(),20.0);
+        assert_eq!(c.to_record(false).receivable,Some(20.0));
+        assert_eq!(c.to_record(true).receivable,None);
+    }
+
+    #[test]
+    fn remove_funds_overdraft() {
+        let mut c = Client::new(500);
+
+        c.set_overdraft_limit(50.0);
+        c.add_funds(20.0);
+
+        assert!(c.remove_funds(60.0));
+        assert_eq!(c.available,-40.0);
+        assert_eq!(c.get_credit_used(),40.0);
+        assert!(!c.remove_funds(20.0));
+        assert_eq!(c.to_record(false).credit_used,Some(40.0));
+        assert_eq!(c.to_record(true).credit_used,None);
+    }
+
+    #[test]
+    fn record() {
+        let mut c = Client::new(500);
+        c.add_funds(10.0);
+        c.hold_funds(4.0);
+        c.lock();
+
+        let record = c.to_record(false);
+        assert!(record.validate());
+        let mut loaded = Client::new(500);
+        loaded.load_record(&record);
+        assert_eq!(loaded.to_record(false), record);
+
+        let legacy = c.to_record(true);
+        assert!(legacy.validate());
+        let mut loaded = Client::new(500);
+        loaded.load_record(&legacy);
+        assert!(loaded.is_locked());
+
+        let mut bad = record.clone();
+        bad.total = 10.001;
+        assert!(!bad.validate());
+        let mut bad = record.clone();
+        bad.locked = false;
+        assert!(!bad.validate());
+    }
 }
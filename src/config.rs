@@ -0,0 +1,261 @@
+use crate::policy::Policy;
+use crate::rules::RuleConfig;
+use crate::status::{AccountStatus,StatusMatrix};
+use crate::transaction::TransactionType;
+use crate::types::*;
+use crate::velocity::VelocityLimits;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// Largest number of seconds a setting can have so it fits in a Timestamp
+const MAX_SECONDS: f64 = (Timestamp::MAX / 1000) as f64;
+
+/// Configuration of how a TransactionProcessor applies transactions
+///
+/// Loaded from a TOML file where every section is optional, e.g.
+/// ```toml
+/// [status]
+/// frozen = ["deposit", "dispute", "resolve", "chargeback", "unlock"]
+///
+/// [policy]
+/// lock_after_chargebacks = 3
+/// locked_disputes_move_funds = true
+///
+/// [velocity]
+/// window = 10
+/// max_count = 3
+///
+/// [tiers.premium.velocity]
+/// window = 10
+/// max_total = 10000.0
+///
+/// [[rules]]
+/// rule = "large_amount"
+/// threshold = 10000.0
+///
+/// [timestamps]
+/// reorder_tolerance = 5.0
+/// ```
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Transaction types allowed on an account in each status
+    pub status: StatusMatrix,
+    /// Chargeback and lock policy
+    pub policy: Policy,
+    /// Caps on withdrawals for Clients without a tier
+    pub velocity: VelocityLimits,
+    /// Settings for Clients in each tier
+    pub tiers: HashMap<String,Tier>,
+    /// Active built-in rules, in the order they're checked
+    pub rules: Vec<RuleConfig>,
+    /// How transaction timestamps are handled
+    pub timestamps: Timestamps
+}
+
+/// How transaction timestamps are handled
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timestamps {
+    /// Seconds a transaction may arrive after later ones and still be
+    /// processed in timestamp order, 0 to process transactions as they arrive
+    pub reorder_tolerance: f64
+}
+
+impl Timestamps {
+    /// Get the reorder tolerance in milliseconds
+    pub fn get_tolerance(&self) -> Timestamp {
+        (self.reorder_tolerance * 1000.0).round() as Timestamp
+    }
+}
+
+/// Settings for Clients in a tier
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tier {
+    /// Caps on withdrawals
+    pub velocity: VelocityLimits
+}
+
+/// Settings for a single Client, read from a client config CSV, e.g.
+/// ```csv
+/// client,overdraft_limit,tier
+/// 1,500.0,premium
+/// ```
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ClientConfig {
+    pub client: ClientID,
+    /// How far the Client's available funds may go below 0
+    pub overdraft_limit: Option<f64>,
+    /// Tier the Client's settings are taken from
+    #[serde(default)]
+    pub tier: Option<String>
+}
+
+impl ClientConfig {
+    /// Validate the settings
+    pub fn validate(&self) -> bool {
+        self.overdraft_limit.is_none_or(|l| l >= 0.0)
+    }
+}
+
+impl Config {
+    /// Parse a Config from a TOML string
+    ///
+    /// Note: Settings in seconds must be from 0 up to MAX_SECONDS
+    pub fn from_toml(s: &str) -> Result<Self,String> {
+        let config: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that every setting in seconds is a number that fits in a
+    /// Timestamp
+    fn validate(&self) -> Result<(),String> {
+        let mut seconds: Vec<(String,Option<f64>)> = vec![
+            ("timestamps.reorder_tolerance".into(), Some(self.timestamps.reorder_tolerance)),
+            ("policy.dispute_window".into(), self.policy.dispute_window),
+            ("policy.dispute_deadline".into(), self.policy.dispute_deadline),
+            ("velocity.window_secs".into(), self.velocity.window_secs)
+        ];
+        for (name, tier) in &self.tiers {
+            seconds.push((format!("tiers.{}.velocity.window_secs", name),
+                          tier.velocity.window_secs));
+        }
+
+        for (name, secs) in seconds {
+            if let Some(secs) = secs.filter(|s| !(0.0..=MAX_SECONDS).contains(s)) {
+                return Err(format!("{} must be from 0 to {} seconds, not {}",
+                                   name, MAX_SECONDS, secs));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if a transaction type is allowed on an account with a status
+    ///
+    /// A type is allowed if either the status matrix or, for locked
+    /// accounts, the policy allows it
+    pub fn allows(&self, status: AccountStatus, typ: TransactionType) -> bool {
+        self.status.allows(status, typ) ||
+        ((status == AccountStatus::Locked) && self.policy.allows_locked(typ))
+    }
+
+    /// Get the caps on withdrawals for a Client in a tier
+    ///
+    /// Note: Clients in an unknown tier get the caps for Clients without one
+    pub fn velocity_limits(&self, tier: Option<&str>) -> &VelocityLimits {
+        tier.and_then(|t| self.tiers.get(t))
+            .map_or(&self.velocity, |t| &t.velocity)
+    }
+
+    /// Load a Config from a TOML file
+    pub fn load<P>(path: P) -> Result<Self,String>
+            where P: AsRef<Path> {
+        let s = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.as_ref().display(), e))?;
+        Self::from_toml(&s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::status::AccountStatus;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn from_toml() {
+        let c = Config::from_toml(
+            "[status]\n\
+             locked = [\"dispute\", \"resolve\", \"unlock\"]\n").unwrap();
+
+        assert!(c.status.allows(AccountStatus::Locked, Resolve));
+        assert!(!c.status.allows(AccountStatus::Locked, Deposit));
+        // sections left out keep their defaults
+        assert!(c.status.allows(AccountStatus::Active, Deposit));
+    }
+
+    #[test]
+    fn from_toml_empty() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn from_toml_invalid() {
+        assert!(Config::from_toml("[status]\nlocked = [\"teleport\"]\n").is_err());
+        assert_eq!(Config::from_toml("[policy]\ndispute_window = -1.0\n").unwrap_err(),
+                   format!("policy.dispute_window must be from 0 to {} seconds, not -1",
+                           MAX_SECONDS));
+        assert!(Config::from_toml("[policy]\ndispute_deadline = nan\n").is_err());
+        assert!(Config::from_toml("[timestamps]\nreorder_tolerance = inf\n").is_err());
+        assert!(Config::from_toml("[timestamps]\nreorder_tolerance = 1e300\n").is_err());
+        assert!(Config::from_toml("[tiers.vip.velocity]\nwindow_secs = -5.0\n").is_err());
+        assert!(Config::from_toml("[policy]\ndispute_window = 0.0\n").is_ok());
+        assert!(Config::from_toml("[unknown]\n").is_err());
+    }
+
+    #[test]
+    fn from_toml_policy() {
+        let c = Config::from_toml(
+            "[policy]\n\
+             lock_after_chargebacks = 2\n\
+             locked_disputes_move_funds = true\n").unwrap();
+
+        assert_eq!(c.policy.lock_after_chargebacks, 2);
+        assert!(!c.policy.locked_accepts_deposits);
+        assert!(c.allows(AccountStatus::Locked, Resolve));
+        assert!(!c.allows(AccountStatus::Locked, Deposit));
+        assert!(!c.allows(AccountStatus::Frozen, Withdrawal));
+    }
+
+    #[test]
+    fn from_toml_velocity() {
+        let c = Config::from_toml(
+            "[velocity]\n\
+             window = 5\n\
+             max_count = 2\n\
+             \n\
+             [tiers.premium.velocity]\n\
+             window = 10\n\
+             window_secs = 1.5\n\
+             max_total = 1000.0\n\
+             freeze = true\n").unwrap();
+
+        assert_eq!(c.velocity_limits(None).max_count, Some(2));
+        assert_eq!(c.velocity_limits(Some("unknown")).window, 5);
+        let premium = c.velocity_limits(Some("premium"));
+        assert_eq!(premium.window, 10);
+        assert_eq!(premium.get_time_window(), Some(1500));
+        assert_eq!(c.velocity_limits(None).get_time_window(), None);
+        assert_eq!(premium.max_count, None);
+        assert_eq!(premium.max_total, Some(1000.0));
+        assert!(premium.freeze);
+    }
+
+    #[test]
+    fn from_toml_rules() {
+        use crate::rules::*;
+        let c = Config::from_toml(
+            "[[rules]]\n\
+             rule = \"large_amount\"\n\
+             threshold = 500.0\n\
+             \n\
+             [[rules]]\n\
+             rule = \"many_disputes\"\n\
+             max = 3\n\
+             action = \"lock\"\n").unwrap();
+
+        assert_eq!(c.rules, vec![
+            RuleConfig::LargeAmount(LargeAmount { threshold: 500.0, action: Action::Flag }),
+            RuleConfig::ManyDisputes(ManyDisputes { max: 3, action: Action::Lock })
+        ]);
+        assert!(Config::from_toml("[[rules]]\nrule = \"teleport\"\n").is_err());
+        assert!(Config::from_toml(
+            "[[rules]]\nrule = \"many_disputes\"\nmax = 1\nmin = 0\n").is_err());
+    }
+}
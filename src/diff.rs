@@ -0,0 +1,214 @@
+use crate::types::*;
+use crate::client::{ClientRecord,serialize_f64_to_4,serialize_opt_f64_to_4};
+use crate::reconcile::ROUNDING;
+use crate::status::AccountStatus;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+/// A value of a field in a Client's account state
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    Amount(#[serde(serialize_with = "serialize_f64_to_4")] f64),
+    Flag(bool),
+    Status(AccountStatus)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Amount(a) => write!(f, "{:.4}", a),
+            Value::Flag(b) => write!(f, "{}", b),
+            Value::Status(s) => write!(f, "{}", s)
+        }
+    }
+}
+
+/// A change to one field of a Client's account state
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: Value,
+    pub after: Value,
+    #[serde(skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_opt_f64_to_4")]
+    pub delta: Option<f64>
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.field, self.before, self.after)?;
+        if let Some(delta) = self.delta {
+            write!(f, " ({:+.4})", delta)?;
+        }
+        Ok(())
+    }
+}
+
+/// A difference in a Client's account state between two sets of balances
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum ClientDiff {
+    /// The Client is only in the later balances
+    Added(ClientRecord),
+    /// The Client is only in the earlier balances
+    Removed(ClientRecord),
+    /// Fields of the Client's account state changed
+    Changed {
+        client: ClientID,
+        fields: Vec<FieldChange>
+    }
+}
+
+impl ClientDiff {
+    /// Get the ID of the Client the difference is for
+    pub fn get_client_id(&self) -> ClientID {
+        match self {
+            ClientDiff::Added(r) | ClientDiff::Removed(r) => r.client,
+            ClientDiff::Changed { client, .. } => *client
+        }
+    }
+}
+
+impl fmt::Display for ClientDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientDiff::Added(r) => {
+                write!(f, "client {}: added, available {:.4}, held {:.4}, total {:.4}, \
+                           locked {}", r.client, r.available, r.held, r.total, r.locked)
+            },
+            ClientDiff::Removed(r) => write!(f, "client {}: removed", r.client),
+            ClientDiff::Changed { client, fields } => {
+                let fields: Vec<String> = fields.iter().map(|c| c.to_string()).collect();
+                write!(f, "client {}: {}", client, fields.join(", "))
+            }
+        }
+    }
+}
+
+/// Get the fields that changed between two account states of a Client
+///
+/// Note: Optional fields are only compared if both states have them
+fn changed_fields(before: &ClientRecord, after: &ClientRecord) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+
+    let amounts = [("available", Some(before.available), Some(after.available)),
+                   ("held", Some(before.held), Some(after.held)),
+                   ("total", Some(before.total), Some(after.total)),
+                   ("receivable", before.receivable, after.receivable),
+                   ("credit_used", before.credit_used, after.credit_used)];
+    for (field, before, after) in amounts {
+        if let (Some(b), Some(a)) = (before, after) {
+            if (a - b).abs() > ROUNDING {
+                changes.push(FieldChange {
+                    field,
+                    before: Value::Amount(b),
+                    after: Value::Amount(a),
+                    delta: Some(a - b)
+                });
+            }
+        }
+    }
+    if before.locked != after.locked {
+        changes.push(FieldChange {
+            field: "locked",
+            before: Value::Flag(before.locked),
+            after: Value::Flag(after.locked),
+            delta: None
+        });
+    }
+    if let (Some(b), Some(a)) = (before.status, after.status) {
+        if b != a {
+            changes.push(FieldChange {
+                field: "status",
+                before: Value::Status(b),
+                after: Value::Status(a),
+                delta: None
+            });
+        }
+    }
+
+    changes
+}
+
+/// Compare two sets of Client account states by Client ID
+///
+/// Differences are in order of Client ID, and Clients that didn't change are
+/// left out
+pub fn diff(before: &[ClientRecord], after: &[ClientRecord]) -> Vec<ClientDiff> {
+    let before: BTreeMap<ClientID,&ClientRecord> = before.iter()
+        .map(|r| (r.client, r))
+        .collect();
+    let after: BTreeMap<ClientID,&ClientRecord> = after.iter()
+        .map(|r| (r.client, r))
+        .collect();
+
+    let mut diffs = Vec::new();
+    for (id, b) in &before {
+        match after.get(id) {
+            Some(a) => {
+                let fields = changed_fields(b, a);
+                if !fields.is_empty() {
+                    diffs.push(ClientDiff::Changed { client: *id, fields });
+                }
+            },
+            None => diffs.push(ClientDiff::Removed((*b).clone()))
+        }
+    }
+    for (_, a) in after.iter().filter(|(id, _)| !before.contains_key(id)) {
+        diffs.push(ClientDiff::Added((*a).clone()));
+    }
+    diffs.sort_by_key(|d| d.get_client_id());
+
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reconcile::read_records;
+
+    #[test]
+    fn diff_records() {
+        let before = "client,available,held,total,locked,status\n\
+                      1,10.0,0.0,10.0,false,active\n\
+                      2,5.0,1.0,6.0,false,active\n\
+                      3,1.0,0.0,1.0,false,active";
+        let after = "client,available,held,total,locked,status\n\
+                     1,10.0,0.0,10.0,false,active\n\
+                     2,5.5,0.0,5.5,true,locked\n\
+                     4,1.0,0.0,1.0,false,active";
+        let before = read_records(before.as_bytes()).unwrap();
+        let after = read_records(after.as_bytes()).unwrap();
+
+        let diffs = diff(&before, &after);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(diffs[0].to_string(),
+                   "client 2: available 5.0000 -> 5.5000 (+0.5000), \
+                    held 1.0000 -> 0.0000 (-1.0000), total 6.0000 -> 5.5000 (-0.5000), \
+                    locked false -> true, status active -> locked");
+        assert_eq!(diffs[1], ClientDiff::Removed(before[2].clone()));
+        assert_eq!(diffs[2], ClientDiff::Added(after[2].clone()));
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn diff_json() {
+        let before = read_records("client,available,held,total,locked\n1,1.0,0.0,1.0,false".as_bytes()).unwrap();
+        let after = read_records("client,available,held,total,locked\n1,2.0,0.0,2.0,true".as_bytes()).unwrap();
+
+        let json = serde_json::to_string(&diff(&before, &after)[0]).unwrap();
+        assert_eq!(json,
+                   "{\"change\":\"changed\",\"client\":1,\"fields\":[\
+                    {\"field\":\"available\",\"before\":\"1.0000\",\"after\":\"2.0000\",\"delta\":\"1.0000\"},\
+                    {\"field\":\"total\",\"before\":\"1.0000\",\"after\":\"2.0000\",\"delta\":\"1.0000\"},\
+                    {\"field\":\"locked\",\"before\":false,\"after\":true}]}");
+        let json = serde_json::to_string(&diff(&before, &[])[0]).unwrap();
+        assert_eq!(json,
+                   "{\"change\":\"removed\",\"client\":1,\"available\":\"1.0000\",\
+                    \"held\":\"0.0000\",\"total\":\"1.0000\",\"locked\":false}");
+    }
+}
@@ -0,0 +1,710 @@
+use crate::client::Client;
+use crate::history::RejectReason;
+use crate::rules::{Rule,Verdict};
+use crate::transaction::Transaction;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Names that can be compared against the type of a transaction
+const TYPES: &[&str] = &[
+    "deposit", "withdrawal", "transfer", "dispute", "resolve", "chargeback",
+    "authorize", "capture", "void", "unlock", "freeze", "close", "adjust",
+    "limit"
+];
+
+/// Names that can be compared against the status of an account
+const STATUSES: &[&str] = &["active", "frozen", "locked", "closed", "dormant"];
+
+/// An error in a rule file with the position it was found at
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A rule written as `when <condition> then <action> ["message"]`, e.g.
+/// ```text
+/// when type == withdrawal and amount > 10000 and client.disputes >= 2
+///     then reject "high risk"
+/// ```
+///
+/// Actions are allow, flag, reject and lock, where allow processes the
+/// transaction without checking any later rules. The message, or the line of the
+/// rule if there is none, is what the rule flags transactions under
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslRule {
+    name: String,
+    condition: Expr,
+    action: Action
+}
+
+/// Parse every rule in a rule file
+pub fn parse_rules(src: &str) -> Result<Vec<DslRule>,ParseError> {
+    let tokens = lex(src)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut rules = Vec::new();
+    while parser.peek().kind != Kind::End {
+        rules.push(parser.rule()?);
+    }
+
+    Ok(rules)
+}
+
+/// Load every rule in a rule file
+pub fn load_rules<P>(path: P) -> Result<Vec<DslRule>,String>
+        where P: AsRef<Path> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_rules(&src).map_err(|e| format!("{}:{}", path.display(), e))
+}
+
+impl Rule for DslRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, trans: &Transaction, client: &Client) -> Verdict {
+        if self.condition.eval(trans, client) != Value::Bool(true) {
+            return Verdict::Allow;
+        }
+
+        match self.action {
+            Action::Allow => Verdict::Pass,
+            Action::Flag => Verdict::Flag,
+            Action::Reject => Verdict::Reject(RejectReason::RuleRejected),
+            Action::Lock => Verdict::Lock(RejectReason::RuleRejected)
+        }
+    }
+}
+
+/// What a rule does with a transaction matching its condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Allow,
+    Flag,
+    Reject,
+    Lock
+}
+
+/// Fields of a transaction or its Client that a condition can refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Type,
+    Amount,
+    Tx,
+    Client,
+    Counterparty,
+    Available,
+    Held,
+    Total,
+    Receivable,
+    CreditUsed,
+    OverdraftLimit,
+    Disputes,
+    Chargebacks,
+    Status,
+    Tier
+}
+
+impl Field {
+    /// Get the field with a name
+    fn from_name(name: &str) -> Option<Self> {
+        use Field::*;
+        let field = match name {
+            "type" => Type,
+            "amount" => Amount,
+            "tx" => Tx,
+            "client" => Client,
+            "counterparty" => Counterparty,
+            "client.available" => Available,
+            "client.held" => Held,
+            "client.total" => Total,
+            "client.receivable" => Receivable,
+            "client.credit_used" => CreditUsed,
+            "client.overdraft_limit" => OverdraftLimit,
+            "client.disputes" => Disputes,
+            "client.chargebacks" => Chargebacks,
+            "client.status" => Status,
+            "client.tier" => Tier,
+            _ => return None
+        };
+        Some(field)
+    }
+
+    /// Get the kind of value the field has
+    fn kind(&self) -> ValueKind {
+        use Field::*;
+        match self {
+            Type | Status | Tier => ValueKind::Str,
+            _ => ValueKind::Number
+        }
+    }
+
+    /// Get the names the field can be equal to, if limited
+    fn names(&self) -> Option<(&'static [&'static str],&'static str)> {
+        match self {
+            Field::Type => Some((TYPES, "transaction type")),
+            Field::Status => Some((STATUSES, "account status")),
+            _ => None
+        }
+    }
+
+    /// Get the value of the field for a transaction and its Client
+    fn get(&self, trans: &Transaction, client: &Client) -> Value {
+        use Field::*;
+        match self {
+            Type => Value::Str(format!("{:?}", trans.get_type()).to_lowercase()),
+            Amount => trans.get_amount().map_or(Value::None, Value::Number),
+            Tx => Value::Number(trans.get_id() as f64),
+            Client => Value::Number(trans.get_client_id() as f64),
+            Counterparty => {
+                trans.get_counterparty().map_or(Value::None, |c| Value::Number(c as f64))
+            },
+            Available => Value::Number(client.get_available_funds()),
+            Held => Value::Number(client.get_held_funds()),
+            Total => Value::Number(client.get_total_funds()),
+            Receivable => Value::Number(client.get_receivable()),
+            CreditUsed => Value::Number(client.get_credit_used()),
+            OverdraftLimit => Value::Number(client.get_overdraft_limit()),
+            Disputes => Value::Number(client.get_disputes() as f64),
+            Chargebacks => Value::Number(client.get_chargebacks() as f64),
+            Status => Value::Str(client.get_status().to_string()),
+            Tier => client.get_tier().map_or(Value::None, |t| Value::Str(t.to_string()))
+        }
+    }
+}
+
+/// Comparison operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+/// A condition or part of one
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Field(Field),
+    Literal(Value),
+    Not(Box<Expr>),
+    And(Box<Expr>,Box<Expr>),
+    Or(Box<Expr>,Box<Expr>),
+    Cmp(Box<Expr>,CmpOp,Box<Expr>)
+}
+
+/// The value of an Expr
+///
+/// Note: Missing fields, e.g. the amount of a dispute, are None
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    None
+}
+
+/// The kinds of Value an Expr can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Number,
+    Str,
+    Bool
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueKind::Number => write!(f, "a number"),
+            ValueKind::Str => write!(f, "a name"),
+            ValueKind::Bool => write!(f, "a condition")
+        }
+    }
+}
+
+impl Expr {
+    /// Get the kind of value the Expr has
+    ///
+    /// Note: A missing field is still of its field's kind
+    fn kind(&self) -> ValueKind {
+        match self {
+            Expr::Field(field) => field.kind(),
+            Expr::Literal(Value::Number(_)) => ValueKind::Number,
+            Expr::Literal(Value::Str(_)) => ValueKind::Str,
+            _ => ValueKind::Bool
+        }
+    }
+
+    /// Evaluate the Expr for a transaction and its Client
+    ///
+    /// Note: Comparing values of different kinds is only ever true for !=
+    fn eval(&self, trans: &Transaction, client: &Client) -> Value {
+        match self {
+            Expr::Field(field) => field.get(trans, client),
+            Expr::Literal(value) => value.clone(),
+            Expr::Not(e) => Value::Bool(e.eval(trans, client) != Value::Bool(true)),
+            Expr::And(l, r) => {
+                Value::Bool((l.eval(trans, client) == Value::Bool(true)) &&
+                            (r.eval(trans, client) == Value::Bool(true)))
+            },
+            Expr::Or(l, r) => {
+                Value::Bool((l.eval(trans, client) == Value::Bool(true)) ||
+                            (r.eval(trans, client) == Value::Bool(true)))
+            },
+            Expr::Cmp(l, op, r) => {
+                let ordering = match (l.eval(trans, client), r.eval(trans, client)) {
+                    (Value::Number(l), Value::Number(r)) => l.partial_cmp(&r),
+                    (Value::Str(l), Value::Str(r)) => Some(l.cmp(&r)),
+                    (Value::Bool(l), Value::Bool(r)) => Some(l.cmp(&r)),
+                    _ => None
+                };
+                Value::Bool(match ordering {
+                    Some(o) => match op {
+                        CmpOp::Eq => o.is_eq(),
+                        CmpOp::Ne => o.is_ne(),
+                        CmpOp::Lt => o.is_lt(),
+                        CmpOp::Le => o.is_le(),
+                        CmpOp::Gt => o.is_gt(),
+                        CmpOp::Ge => o.is_ge()
+                    },
+                    None => *op == CmpOp::Ne
+                })
+            }
+        }
+    }
+}
+
+/// Kinds of tokens in a rule file
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Op(CmpOp),
+    LParen,
+    RParen,
+    End
+}
+
+/// A token and the position it starts at
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: Kind,
+    line: usize,
+    column: usize
+}
+
+/// Splits a rule file into tokens
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize
+}
+
+impl Lexer {
+    /// Get the next char without taking it
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Take the next char, keeping track of the position
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Take chars for as long as they match
+    fn take_while<F>(&mut self, f: F) -> String
+            where F: Fn(char) -> bool {
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(|c| f(*c)) {
+            self.bump();
+            s.push(c);
+        }
+        s
+    }
+}
+
+/// Split a rule file into tokens
+///
+/// Note: `#` starts a comment running to the end of the line, and a number
+/// can start with `-`
+fn lex(src: &str) -> Result<Vec<Token>,ParseError> {
+    let mut lexer = Lexer {
+        chars: src.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1
+    };
+    let mut tokens = Vec::new();
+
+    while let Some(c) = lexer.peek() {
+        let (line, column) = (lexer.line, lexer.column);
+        let error = |message: String| ParseError { line, column, message };
+
+        let kind = match c {
+            c if c.is_whitespace() => {
+                lexer.bump();
+                continue;
+            },
+            '#' => {
+                lexer.take_while(|c| c != '\n');
+                continue;
+            },
+            '(' | ')' => {
+                lexer.bump();
+                if c == '(' { Kind::LParen } else { Kind::RParen }
+            },
+            '=' | '!' | '<' | '>' => {
+                lexer.bump();
+                let eq = lexer.peek() == Some('=');
+                if eq {
+                    lexer.bump();
+                }
+                match (c, eq) {
+                    ('=', true) => Kind::Op(CmpOp::Eq),
+                    ('!', true) => Kind::Op(CmpOp::Ne),
+                    ('<', true) => Kind::Op(CmpOp::Le),
+                    ('>', true) => Kind::Op(CmpOp::Ge),
+                    ('<', false) => Kind::Op(CmpOp::Lt),
+                    ('>', false) => Kind::Op(CmpOp::Gt),
+                    _ => return Err(error(format!("expected '{}='", c)))
+                }
+            },
+            '"' => {
+                lexer.bump();
+                let s = lexer.take_while(|c| c != '"' && c != '\n');
+                if lexer.bump() != Some('"') {
+                    return Err(error("unterminated string".to_string()));
+                }
+                Kind::Str(s)
+            },
+            c if c.is_ascii_digit() || c == '-' => {
+                lexer.bump();
+                let s = format!("{}{}", c, lexer.take_while(|c| c.is_ascii_digit() || c == '.'));
+                let n = s.parse()
+                    .map_err(|_| error(format!("invalid number '{}'", s)))?;
+                Kind::Number(n)
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                Kind::Ident(lexer.take_while(|c| c.is_ascii_alphanumeric() ||
+                                                 c == '_' || c == '.'))
+            },
+            c => return Err(error(format!("unexpected character '{}'", c)))
+        };
+
+        tokens.push(Token { kind, line, column });
+    }
+
+    tokens.push(Token { kind: Kind::End, line: lexer.line, column: lexer.column });
+    Ok(tokens)
+}
+
+/// Create an error at the position of a token
+fn error_at(token: &Token, message: String) -> ParseError {
+    ParseError { line: token.line, column: token.column, message }
+}
+
+/// Builds rules from tokens
+///
+/// Conditions are checked as they're parsed, so one that could never match,
+/// e.g. `amount` on its own or a comparison with a misspelt type, is an error
+///
+/// Grammar:
+/// ```text
+/// rule   := "when" or "then" action [string]
+/// action := "allow" | "flag" | "reject" | "lock"
+/// or     := and ("or" and)*
+/// and    := not ("and" not)*
+/// not    := "not" not | cmp
+/// cmp    := value [op value]
+/// value  := number | string | "true" | "false" | field | symbol | "(" or ")"
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+impl Parser {
+    /// Get the next token without taking it
+    fn peek(&self) -> &Token {
+        // the End token is never taken so there's always a next token
+        &self.tokens[self.pos]
+    }
+
+    /// Take the next token
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != Kind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Create an error at the position of the next token
+    fn error(&self, message: String) -> ParseError {
+        error_at(self.peek(), message)
+    }
+
+    /// Parse a condition, which must be true or false, e.g. not a field on
+    /// its own
+    fn condition<F>(&mut self, parse: F) -> Result<Expr,ParseError>
+            where F: Fn(&mut Self) -> Result<Expr,ParseError> {
+        let start = self.peek().clone();
+        let expr = parse(self)?;
+        match expr.kind() {
+            ValueKind::Bool => Ok(expr),
+            kind => Err(error_at(&start, format!("expected a condition, found {}", kind)))
+        }
+    }
+
+    /// Check if the next token is a keyword
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, Kind::Ident(s) if s == keyword)
+    }
+
+    /// Take a keyword that must come next
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(),ParseError> {
+        if self.at_keyword(keyword) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", keyword)))
+        }
+    }
+
+    fn rule(&mut self) -> Result<DslRule,ParseError> {
+        let line = self.peek().line;
+        self.expect_keyword("when")?;
+        let condition = self.condition(Self::or)?;
+        self.expect_keyword("then")?;
+
+        let action = match &self.peek().kind {
+            Kind::Ident(s) if s == "allow" => Action::Allow,
+            Kind::Ident(s) if s == "flag" => Action::Flag,
+            Kind::Ident(s) if s == "reject" => Action::Reject,
+            Kind::Ident(s) if s == "lock" => Action::Lock,
+            _ => return Err(self.error("expected an action: allow, flag, reject or lock".to_string()))
+        };
+        self.bump();
+
+        let name = match &self.peek().kind {
+            Kind::Str(s) => {
+                let s = s.clone();
+                self.bump();
+                s
+            },
+            _ => format!("line {}", line)
+        };
+
+        Ok(DslRule { name, condition, action })
+    }
+
+    fn or(&mut self) -> Result<Expr,ParseError> {
+        let mut expr = self.and()?;
+        while self.at_keyword("or") {
+            let expr_kind = expr.kind();
+            if expr_kind != ValueKind::Bool {
+                return Err(self.error(format!("expected a condition before 'or', found {}",
+                                              expr_kind)));
+            }
+            self.bump();
+            expr = Expr::Or(Box::new(expr), Box::new(self.condition(Self::and)?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr,ParseError> {
+        let mut expr = self.not()?;
+        while self.at_keyword("and") {
+            let expr_kind = expr.kind();
+            if expr_kind != ValueKind::Bool {
+                return Err(self.error(format!("expected a condition before 'and', found {}",
+                                              expr_kind)));
+            }
+            self.bump();
+            expr = Expr::And(Box::new(expr), Box::new(self.condition(Self::not)?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr,ParseError> {
+        if self.at_keyword("not") {
+            self.bump();
+            Ok(Expr::Not(Box::new(self.condition(Self::not)?)))
+        } else {
+            self.cmp()
+        }
+    }
+
+    fn cmp(&mut self) -> Result<Expr,ParseError> {
+        let left_token = self.peek().clone();
+        let left = self.value()?;
+        let op = match self.peek().kind {
+            Kind::Op(op) => op,
+            _ => return Ok(left)
+        };
+        let op_token = self.bump();
+        let right_token = self.peek().clone();
+        let right = self.value()?;
+
+        if left.kind() != right.kind() {
+            return Err(error_at(&op_token, format!("can't compare {} with {}",
+                                                   left.kind(), right.kind())));
+        }
+        // a field limited to some names must be compared with one of them
+        for (field, name, token) in [(&left, &right, &right_token), (&right, &left, &left_token)] {
+            if let (Expr::Field(field), Expr::Literal(Value::Str(name))) = (field, name) {
+                if let Some((names, what)) = field.names() {
+                    if !names.contains(&name.as_str()) {
+                        return Err(error_at(token, format!("unknown {} '{}'", what, name)));
+                    }
+                }
+            }
+        }
+
+        Ok(Expr::Cmp(Box::new(left), op, Box::new(right)))
+    }
+
+    fn value(&mut self) -> Result<Expr,ParseError> {
+        let token = self.peek().clone();
+        let expr = match token.kind {
+            Kind::Number(n) => Expr::Literal(Value::Number(n)),
+            Kind::Str(s) => Expr::Literal(Value::Str(s)),
+            Kind::LParen => {
+                self.bump();
+                let expr = self.or()?;
+                if self.peek().kind != Kind::RParen {
+                    return Err(self.error("expected ')'".to_string()));
+                }
+                expr
+            },
+            Kind::Ident(s) if s == "true" || s == "false" => {
+                Expr::Literal(Value::Bool(s == "true"))
+            },
+            Kind::Ident(s) => {
+                if let Some(field) = Field::from_name(&s) {
+                    Expr::Field(field)
+                } else if TYPES.contains(&s.as_str()) || STATUSES.contains(&s.as_str()) {
+                    Expr::Literal(Value::Str(s))
+                } else {
+                    return Err(self.error(format!("unknown name '{}'", s)));
+                }
+            },
+            _ => return Err(self.error("expected a value".to_string()))
+        };
+        self.bump();
+
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(src: &str, trans: &Transaction, client: &Client) -> Verdict {
+        parse_rules(src).unwrap()[0].check(trans, client)
+    }
+
+    #[test]
+    fn parse_example() {
+        let rules = parse_rules(
+            "# high risk withdrawals\n\
+             when type == withdrawal and amount > 10000 and client.disputes >= 2\n\
+             \x20   then reject \"high risk\"\n\
+             when not (client.status == active) then flag\n").unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name(), "high risk");
+        assert_eq!(rules[0].action, Action::Reject);
+        assert_eq!(rules[1].name(), "line 4");
+        assert_eq!(rules[1].action, Action::Flag);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |src: &str| parse_rules(src).unwrap_err().to_string();
+
+        assert_eq!(error("when amount > 5\nthen"), "2:5: expected an action: allow, flag, reject or lock");
+        assert_eq!(error("when amout > 5 then flag"), "1:6: unknown name 'amout'");
+        assert_eq!(error("when client.foo > 5 then flag"), "1:6: unknown name 'client.foo'");
+        assert_eq!(error("when amount > 5 flag"), "1:17: expected 'then'");
+        assert_eq!(error("when (amount > 5 then flag"), "1:18: expected ')'");
+        assert_eq!(error("when amount = 5 then flag"), "1:13: expected '=='");
+        assert_eq!(error("when type == \"deposit then flag"), "1:14: unterminated string");
+        assert_eq!(error("when amount > 1.2.3 then flag"), "1:15: invalid number '1.2.3'");
+        assert_eq!(error("when amount > - 5 then flag"), "1:15: invalid number '-'");
+        assert_eq!(error("when amount $ 5 then flag"), "1:13: unexpected character '$'");
+        assert_eq!(error("flag"), "1:1: expected 'when'");
+        assert_eq!(error("when amount then reject"), "1:6: expected a condition, found a number");
+        assert_eq!(error("when client.tier or amount > 5 then flag"),
+                   "1:18: expected a condition before 'or', found a name");
+        assert_eq!(error("when amount > 5 and 5 then flag"),
+                   "1:21: expected a condition, found a number");
+        assert_eq!(error("when not type then flag"), "1:10: expected a condition, found a name");
+        assert_eq!(error("when amount == \"5\" then flag"),
+                   "1:13: can't compare a number with a name");
+        assert_eq!(error("when type == \"withdrawl\" then flag"),
+                   "1:14: unknown transaction type 'withdrawl'");
+        assert_eq!(error("when active != client.status and type == active then flag"),
+                   "1:42: unknown transaction type 'active'");
+        assert_eq!(error("when client.status == \"suspended\" then flag"),
+                   "1:23: unknown account status 'suspended'");
+    }
+
+    #[test]
+    fn eval() {
+        let mut c = Client::new(500);
+        c.add_dispute();
+        c.add_dispute();
+        let w = Transaction::new_withdrawl(500,600,20000.0,false);
+        let d = Transaction::new_deposit(500,601,20000.0,false);
+        let rule = "when type == withdrawal and amount > 10000 and client.disputes >= 2 \
+                    then reject \"high risk\"";
+
+        assert_eq!(check(rule, &w, &c), Verdict::Reject(RejectReason::RuleRejected));
+        assert_eq!(check(rule, &d, &c), Verdict::Allow);
+        assert_eq!(check("when amount < 5 or client.status != locked then lock", &d, &c),
+                   Verdict::Lock(RejectReason::RuleRejected));
+        assert_eq!(check("when not client.tier == \"vip\" then flag", &d, &c), Verdict::Flag);
+        assert_eq!(check("when type == deposit then allow", &d, &c), Verdict::Pass);
+        assert_eq!(check("when client.available > -1 then flag", &d, &c), Verdict::Flag);
+        assert_eq!(check("when client.available > -0.5 and client.available < 0.5 then flag",
+                         &d, &c), Verdict::Flag);
+        assert_eq!(check("when client.available < -1 then flag", &d, &c), Verdict::Allow);
+    }
+
+    #[test]
+    fn eval_missing() {
+        let c = Client::new(500);
+        let t = Transaction::new_dispute(500,600);
+
+        assert_eq!(check("when amount > 0 then flag", &t, &c), Verdict::Allow);
+        assert_eq!(check("when amount != 0 then flag", &t, &c), Verdict::Flag);
+        assert_eq!(check("when type == \"dispute\" then flag", &t, &c), Verdict::Flag);
+    }
+}
@@ -0,0 +1,333 @@
+use crate::types::*;
+use crate::processor::TransactionProcessorErr;
+use crate::reference::ReferenceModel;
+use crate::transaction::{Transaction,TransactionType};
+
+use std::io;
+
+use rand::rngs::StdRng;
+use rand::{Rng,SeedableRng};
+
+/// Largest deposit generated
+const MAX_DEPOSIT: f64 = 1000.0;
+/// Chance a generated deposit or withdrawal is a deposit
+const DEPOSIT_RATE: f64 = 0.6;
+
+/// Settings for a generated workload
+///
+/// Note: The rates are chances per row and should add up to no more than 1.
+/// Every other row is a deposit or withdrawal
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadConfig {
+    /// Number of clients, with IDs counting up from 1
+    pub clients: ClientID,
+    /// Number of transactions
+    pub rows: usize,
+    /// Chance of disputing an earlier deposit or withdrawal
+    pub dispute_rate: f64,
+    /// Chance of resolving an earlier dispute
+    pub resolve_rate: f64,
+    /// Chance of charging back an earlier dispute
+    pub chargeback_rate: f64,
+    /// Chance of a transaction the processor should reject, e.g. a dispute of
+    /// an unknown transaction or a withdrawal of more than is available
+    pub error_rate: f64,
+    /// Seed for the random number generator, the same seed always gives the
+    /// same workload
+    pub seed: u64
+}
+
+impl WorkloadConfig {
+    /// Validate the settings
+    pub fn validate(&self) -> bool {
+        let rates = [self.dispute_rate, self.resolve_rate, self.chargeback_rate,
+                     self.error_rate];
+        (self.clients > 0) &&
+        rates.iter().all(|r| (0.0..=1.0).contains(r)) &&
+        (rates.iter().sum::<f64>() <= 1.0)
+    }
+}
+
+impl Default for WorkloadConfig {
+    /// 10,000 transactions across 1,000 clients with a few disputes and errors
+    fn default() -> Self {
+        Self {
+            clients: 1000,
+            rows: 10_000,
+            dispute_rate: 0.02,
+            resolve_rate: 0.01,
+            chargeback_rate: 0.005,
+            error_rate: 0.01,
+            seed: 0
+        }
+    }
+}
+
+/// A generated transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Row {
+    pub typ: TransactionType,
+    pub client: ClientID,
+    pub tx: TransactionID,
+    pub amount: Option<f64>
+}
+
+impl Row {
+    /// Format the transaction as a CSV row with the type, client, tx and
+    /// amount columns
+    pub fn to_csv(&self) -> String {
+        let typ = format!("{:?}", self.typ).to_lowercase();
+        match self.amount {
+            Some(amount) => format!("{},{},{},{:.4}", typ, self.client, self.tx, amount),
+            None => format!("{},{},{},", typ, self.client, self.tx)
+        }
+    }
+
+    /// Get the transaction for the row
+    pub fn to_transaction(&self) -> Transaction {
+        use TransactionType::*;
+        let (client, tx, amount) = (self.client, self.tx, self.amount.unwrap_or(0.0));
+        match self.typ {
+            Deposit => Transaction::new_deposit(client, tx, amount, false),
+            Withdrawal => Transaction::new_withdrawl(client, tx, amount, false),
+            Dispute => Transaction::new_dispute(client, tx),
+            Resolve => Transaction::new_resolve(client, tx),
+            Chargeback => Transaction::new_chargeback(client, tx),
+            typ => panic!("{:?} transactions aren't generated", typ)
+        }
+    }
+}
+
+/// Generates a random but repeatable sequence of transactions
+///
+/// Disputes, resolves and chargebacks refer to earlier transactions of the
+/// same Client, except for deliberate errors
+pub struct Generator {
+    config: WorkloadConfig,
+    rng: StdRng,
+    generated: usize,
+    next_tx: TransactionID,
+    payments: Vec<(ClientID,TransactionID)>,
+    disputes: Vec<(ClientID,TransactionID)>
+}
+
+impl Generator {
+    /// Create a Generator for a workload
+    pub fn new(config: WorkloadConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            generated: 0,
+            next_tx: 1,
+            payments: Vec::new(),
+            disputes: Vec::new()
+        }
+    }
+
+    /// Get a random Client ID
+    fn client(&mut self) -> ClientID {
+        self.rng.gen_range(1..=self.config.clients)
+    }
+
+    /// Get a random amount up to a maximum, to 4 decimal places
+    fn amount(&mut self, max: f64) -> f64 {
+        self.rng.gen_range(1..=(max * 10_000.0) as u64) as f64 / 10_000.0
+    }
+
+    /// Get a new transaction ID
+    fn tx(&mut self) -> TransactionID {
+        let tx = self.next_tx;
+        self.next_tx += 1;
+        tx
+    }
+
+    /// Pick a random earlier deposit or withdrawal
+    fn payment(&mut self) -> Option<(ClientID,TransactionID)> {
+        if self.payments.is_empty() {
+            None
+        } else {
+            Some(self.payments[self.rng.gen_range(0..self.payments.len())])
+        }
+    }
+
+    /// Take a random earlier dispute
+    fn take_dispute(&mut self) -> Option<(ClientID,TransactionID)> {
+        if self.disputes.is_empty() {
+            None
+        } else {
+            let i = self.rng.gen_range(0..self.disputes.len());
+            Some(self.disputes.swap_remove(i))
+        }
+    }
+
+    /// Generate a deposit or withdrawal
+    fn payment_row(&mut self) -> Row {
+        let (client, tx) = (self.client(), self.tx());
+        self.payments.push((client, tx));
+        if self.rng.gen_bool(DEPOSIT_RATE) {
+            Row { typ: TransactionType::Deposit, client, tx, amount: Some(self.amount(MAX_DEPOSIT)) }
+        } else {
+            Row {
+                typ: TransactionType::Withdrawal,
+                client,
+                tx,
+                amount: Some(self.amount(MAX_DEPOSIT / 2.0))
+            }
+        }
+    }
+
+    /// Generate a transaction the processor should reject
+    ///
+    /// Note: Some, like a resolve of a transaction that happens to be
+    /// disputed, may still be applied
+    fn error_row(&mut self) -> Row {
+        use TransactionType::*;
+        let row = |typ, client, tx, amount| Row { typ, client, tx, amount };
+        let payment = self.payment();
+        match (self.rng.gen_range(0..5), payment) {
+            // another client's transaction
+            (0, Some((client, tx))) if self.config.clients > 1 => {
+                let other = client % self.config.clients + 1;
+                row(Dispute, other, tx, None)
+            },
+            // a transaction that may not be disputed
+            (1, Some((client, tx))) => {
+                let typ = if self.rng.gen_bool(0.5) { Resolve } else { Chargeback };
+                row(typ, client, tx, None)
+            },
+            // a transaction ID that's already used
+            (2, Some((client, tx))) => row(Deposit, client, tx, Some(self.amount(MAX_DEPOSIT))),
+            // more than any client has
+            (3, _) => {
+                let (client, tx) = (self.client(), self.tx());
+                row(Withdrawal, client, tx, Some(MAX_DEPOSIT * self.config.rows as f64))
+            },
+            // a transaction that doesn't exist
+            _ => {
+                let client = self.client();
+                row(Dispute, client, TransactionID::MAX - self.rng.gen_range(0..1000), None)
+            }
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        use TransactionType::*;
+        if self.generated >= self.config.rows {
+            return None;
+        }
+        self.generated += 1;
+
+        let c = &self.config;
+        let (error, dispute) = (c.error_rate, c.error_rate + c.dispute_rate);
+        let resolve = dispute + c.resolve_rate;
+        let chargeback = resolve + c.chargeback_rate;
+
+        let r: f64 = self.rng.gen();
+        let row = if r < error {
+            self.error_row()
+        } else if r < dispute {
+            match self.payment() {
+                Some((client, tx)) => {
+                    self.disputes.push((client, tx));
+                    Row { typ: Dispute, client, tx, amount: None }
+                },
+                None => self.payment_row()
+            }
+        } else if r < chargeback {
+            let typ = if r < resolve { Resolve } else { Chargeback };
+            match self.take_dispute() {
+                Some((client, tx)) => Row { typ, client, tx, amount: None },
+                None => self.payment_row()
+            }
+        } else {
+            self.payment_row()
+        };
+
+        Some(row)
+    }
+}
+
+/// Write a generated workload as CSV
+///
+/// Returns the reference model with every generated transaction applied, to
+/// get the expected output of processing the workload from
+pub fn write_workload<W>(config: &WorkloadConfig, mut writer: W)
+        -> Result<ReferenceModel,TransactionProcessorErr>
+        where W: io::Write {
+    use TransactionProcessorErr::*;
+
+    let mut model = ReferenceModel::new();
+    writeln!(writer, "type,client,tx,amount").map_err(IOError)?;
+    for row in Generator::new(config.clone()) {
+        writeln!(writer, "{}", row.to_csv()).map_err(IOError)?;
+        model.apply(row.typ, row.client, row.tx, row.amount);
+    }
+    writer.flush().map_err(IOError)?;
+
+    Ok(model)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::TransactionProcessor;
+    use crate::reconcile::reconcile;
+
+    #[test]
+    fn matches_reference() {
+        let config = WorkloadConfig {
+            clients: 20,
+            rows: 5_000,
+            dispute_rate: 0.1,
+            resolve_rate: 0.05,
+            chargeback_rate: 0.01,
+            error_rate: 0.1,
+            seed: 7
+        };
+        assert!(config.validate());
+
+        let mut input = Vec::new();
+        let model = write_workload(&config, &mut input).unwrap();
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_slice()).unwrap();
+        let expected = model.get_records();
+        assert_eq!(expected.len(), 20);
+        assert!(expected.iter().any(|r| r.locked));
+        assert!(expected.iter().any(|r| r.held > 0.0));
+        assert_eq!(reconcile(&tp.get_records(), &expected, 0.0), vec![]);
+        for (a, e) in tp.get_records().iter().zip(&expected) {
+            assert!((a.receivable.unwrap() - e.receivable.unwrap()).abs() < 1e-6);
+        }
+
+        let mut tp = TransactionProcessor::new();
+        for row in Generator::new(config) {
+            tp.process_transaction(row.to_transaction());
+        }
+        assert_eq!(reconcile(&tp.get_records(), &expected, 0.0), vec![]);
+    }
+
+    #[test]
+    fn seeded() {
+        let config = WorkloadConfig { rows: 100, ..WorkloadConfig::default() };
+        let rows: Vec<Row> = Generator::new(config.clone()).collect();
+        assert_eq!(rows.len(), 100);
+        assert_eq!(rows, Generator::new(config.clone()).collect::<Vec<Row>>());
+
+        let other: Vec<Row> = Generator::new(WorkloadConfig { seed: 1, ..config }).collect();
+        assert_ne!(rows, other);
+    }
+
+    #[test]
+    fn validate() {
+        let config = WorkloadConfig::default();
+        assert!(config.validate());
+        assert!(!WorkloadConfig { clients: 0, ..config.clone() }.validate());
+        assert!(!WorkloadConfig { error_rate: -0.1, ..config.clone() }.validate());
+        assert!(!WorkloadConfig { dispute_rate: 0.6, resolve_rate: 0.5, ..config }.validate());
+    }
+}
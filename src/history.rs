@@ -0,0 +1,396 @@
+use crate::types::*;
+use crate::client::{Client,serialize_f64_to_4};
+use crate::input::{serialize_source,Source};
+use crate::status::AccountStatus;
+use crate::time::{format_timestamp,serialize_timestamp};
+use crate::transaction::{Transaction,TransactionType};
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use serde::Serialize;
+
+/// Outcome of a transaction against a Client's account
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Applied,
+    Rejected
+}
+
+/// Reasons a transaction can be rejected
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    AccountLocked,
+    InsufficientFunds,
+    UnknownTransaction,
+    NotDisputed,
+    AlreadyDisputed,
+    ExceedsUndisputed,
+    ExceedsDisputed,
+    NotAuthorized,
+    ExceedsAuthorization,
+    AccountFrozen,
+    AccountClosed,
+    AccountDormant,
+    NotPermitted,
+    AdminNotAllowed,
+    VelocityCount,
+    VelocityTotal,
+    LargeAmount,
+    TooManyDisputes,
+    Structuring,
+    RuleRejected,
+    OutOfOrder,
+    DisputeWindowClosed,
+    InvalidTransaction,
+    DuplicateTransaction
+}
+
+/// The details of a transaction recorded in an Event
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionInfo {
+    pub id: TransactionID,
+    pub typ: TransactionType,
+    pub timestamp: Option<Timestamp>,
+    /// The transaction was made by the processor rather than read from its
+    /// input
+    pub synthetic: bool,
+    /// The input file and line the transaction was read from
+    pub source: Option<Source>
+}
+
+impl TransactionInfo {
+    /// Get the details of a transaction
+    pub fn new(trans: &Transaction) -> Self {
+        Self {
+            id: trans.get_id(),
+            typ: trans.get_type(),
+            timestamp: trans.get_timestamp(),
+            synthetic: trans.is_synthetic(),
+            source: trans.get_source().cloned()
+        }
+    }
+}
+
+/// Balance changes made to a Client's account since the last recorded event
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Changes {
+    pub available: f64,
+    pub held: f64
+}
+
+/// A single entry in a Client's account history
+#[derive(Debug, Serialize, Clone)]
+pub struct Event {
+    client: ClientID,
+    tx: TransactionID,
+    #[serde(rename = "type")]
+    typ: TransactionType,
+    #[serde(serialize_with = "serialize_timestamp")]
+    timestamp: Option<Timestamp>,
+    synthetic: bool,
+    outcome: Outcome,
+    reason: Option<RejectReason>,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    available_change: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    held_change: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    total_change: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    available: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    held: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    total: f64,
+    #[serde(serialize_with = "serialize_f64_to_4")]
+    receivable: f64,
+    status: AccountStatus,
+    flags: String,
+    #[serde(serialize_with = "serialize_source")]
+    source: Option<Source>
+}
+
+impl Event {
+    /// Create an Event from a transaction's result and the Client's balances
+    /// after it was applied
+    ///
+    /// Note: flags are the names of the rules that matched the transaction
+    pub fn new(client: &Client, trans: &TransactionInfo,
+               result: Result<(),RejectReason>, changes: Changes,
+               flags: &[String]) -> Self {
+        let (outcome, reason) = match result {
+            Ok(()) => (Outcome::Applied, None),
+            Err(reason) => (Outcome::Rejected, Some(reason))
+        };
+
+        Self {
+            client: client.get_client_id(),
+            tx: trans.id,
+            typ: trans.typ,
+            timestamp: trans.timestamp,
+            synthetic: trans.synthetic,
+            outcome,
+            reason,
+            available_change: changes.available,
+            held_change: changes.held,
+            total_change: changes.available + changes.held,
+            available: client.get_available_funds(),
+            held: client.get_held_funds(),
+            total: client.get_total_funds(),
+            receivable: client.get_receivable(),
+            status: client.get_status(),
+            flags: flags.join(";"),
+            source: trans.source.clone()
+        }
+    }
+
+    /// Get the transaction ID the event was recorded for
+    #[allow(dead_code)]
+    pub fn get_id(&self) -> TransactionID {
+        self.tx
+    }
+
+    /// Get the type of the transaction the event was recorded for
+    #[allow(dead_code)]
+    pub fn get_type(&self) -> TransactionType {
+        self.typ
+    }
+
+    /// Get the time the transaction was made
+    #[allow(dead_code)]
+    pub fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
+    /// Check if the transaction was made by the processor rather than read
+    /// from its input
+    #[allow(dead_code)]
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic
+    }
+
+    /// Get the outcome of the transaction
+    #[allow(dead_code)]
+    pub fn get_outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// Get the reason the transaction was rejected
+    ///
+    /// Note: Only rejected transactions have a reason
+    #[allow(dead_code)]
+    pub fn get_reason(&self) -> Option<RejectReason> {
+        self.reason
+    }
+
+    /// Get the names of the rules that matched the transaction, separated by
+    /// ';'
+    #[allow(dead_code)]
+    pub fn get_flags(&self) -> &str {
+        &self.flags
+    }
+
+    /// Get the input file and line the transaction was read from
+    #[allow(dead_code)]
+    pub fn get_source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Get the balance changes made by the transaction
+    #[allow(dead_code)]
+    pub fn get_changes(&self) -> Changes {
+        Changes {
+            available: self.available_change,
+            held: self.held_change
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RejectReason::*;
+        let s = match self {
+            AccountLocked => "account locked",
+            InsufficientFunds => "insufficient funds",
+            UnknownTransaction => "unknown transaction",
+            NotDisputed => "transaction not disputed",
+            AlreadyDisputed => "transaction already fully disputed",
+            ExceedsUndisputed => "amount exceeds undisputed amount",
+            ExceedsDisputed => "amount exceeds disputed amount",
+            NotAuthorized => "authorization already settled",
+            ExceedsAuthorization => "amount exceeds remaining authorization",
+            AccountFrozen => "account frozen",
+            AccountClosed => "account closed",
+            AccountDormant => "account dormant",
+            NotPermitted => "transaction type not permitted",
+            AdminNotAllowed => "admin operations not allowed",
+            VelocityCount => "too many withdrawals in window",
+            VelocityTotal => "withdrawals exceed window total",
+            LargeAmount => "amount too large",
+            TooManyDisputes => "too many disputes",
+            Structuring => "possible structuring",
+            RuleRejected => "rejected by rule",
+            OutOfOrder => "timestamp before previous transaction",
+            DisputeWindowClosed => "dispute window closed",
+            InvalidTransaction => "invalid transaction",
+            DuplicateTransaction => "transaction ID already used"
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A Client's most recent applied transactions, with their amounts and
+/// timestamps
+#[derive(Debug, Default)]
+pub struct RecentTransactions {
+    recent: VecDeque<(TransactionType,Option<f64>,Option<Timestamp>)>,
+    newest: Option<Timestamp>
+}
+
+impl RecentTransactions {
+    /// Record a transaction, keeping at most size transactions along with any
+    /// older ones made within span milliseconds of the newest
+    pub fn record(&mut self, typ: TransactionType, amount: Option<f64>,
+                  timestamp: Option<Timestamp>, size: usize,
+                  span: Option<Timestamp>) {
+        self.recent.push_back((typ, amount, timestamp));
+        self.newest = self.newest.max(timestamp);
+        let cutoff = self.newest.zip(span).map(|(ts, span)| ts.saturating_sub(span));
+        while self.recent.len() > size {
+            match (self.recent.front().and_then(|r| r.2), cutoff) {
+                (Some(ts), Some(cutoff)) if ts > cutoff => break,
+                _ => self.recent.pop_front()
+            };
+        }
+    }
+
+    /// Iterate over the last n transactions, most recent first
+    pub fn last(&self, n: usize)
+            -> impl Iterator<Item = (TransactionType,Option<f64>)> + '_ {
+        self.recent.iter().rev().take(n).map(|&(typ, amount, _)| (typ, amount))
+    }
+
+    /// Iterate over the transactions made after a time, most recent first
+    ///
+    /// Note: Transactions without a timestamp are skipped
+    pub fn since(&self, time: Timestamp)
+            -> impl Iterator<Item = (TransactionType,Option<f64>)> + '_ {
+        self.recent.iter().rev()
+            .filter(|r| r.2.is_some())
+            .take_while(move |r| r.2.is_some_and(|ts| ts > time))
+            .map(|&(typ, amount, _)| (typ, amount))
+    }
+}
+
+/// Header matching the column layout of an Event displayed as text
+pub const TEXT_HEADER: &str =
+    "        tx  type        outcome    available       held      total  \
+     -> available       held      total  status   reason";
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let typ = format!("{:?}", self.typ).to_lowercase();
+        let outcome = format!("{:?}", self.outcome).to_lowercase();
+        write!(f, "{:>10}  {:<10}  {:<8}  {:>+10.4} {:>+10.4} {:>+10.4}  \
+                   -> {:>9.4} {:>10.4} {:>10.4}  ",
+               self.tx, typ, outcome,
+               self.available_change, self.held_change, self.total_change,
+               self.available, self.held, self.total)?;
+        match self.reason {
+            Some(reason) => write!(f, "{:<7}  {}", self.status, reason)?,
+            None => write!(f, "{}", self.status)?
+        }
+        if !self.flags.is_empty() {
+            write!(f, "  flagged: {}", self.flags)?;
+        }
+        if let Some(ts) = self.timestamp {
+            write!(f, "  at {}", format_timestamp(ts))?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, "  from {}", source)?;
+        }
+        if self.synthetic {
+            write!(f, "  (automatic)")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_applied() {
+        let mut c = Client::new(500);
+        c.add_funds(100.0);
+
+        let changes = Changes { available: 100.0, held: 0.0 };
+        let mut t = Transaction::new_deposit(500, 600, 100.0, false);
+        t.set_timestamp(Some(1_000));
+        t.set_source(Some(Source { input: "in.csv".into(), line: 2 }));
+        let e = Event::new(&c, &TransactionInfo::new(&t), Ok(()), changes, &[]);
+
+        assert_eq!(e.get_outcome(), Outcome::Applied);
+        assert_eq!(e.get_reason(), None);
+        assert_eq!(e.total_change, 100.0);
+        assert_eq!(e.total, 100.0);
+        assert_eq!(e.get_timestamp(), Some(1_000));
+        assert_eq!(e.get_source().map(|s| s.to_string()), Some("in.csv:2".to_string()));
+        assert!(e.to_string().ends_with("at 1970-01-01T00:00:01Z  from in.csv:2"));
+    }
+
+    #[test]
+    fn new_rejected() {
+        let c = Client::new(500);
+
+        let mut t = Transaction::new_resolve(500, 600);
+        t.set_synthetic();
+        let e = Event::new(&c, &TransactionInfo::new(&t),
+                           Err(RejectReason::InsufficientFunds),
+                           Changes::default(), &["large_amount".to_string()]);
+
+        assert_eq!(e.get_outcome(), Outcome::Rejected);
+        assert_eq!(e.get_reason(), Some(RejectReason::InsufficientFunds));
+        assert_eq!(e.get_changes(), Changes::default());
+        assert_eq!(e.get_flags(), "large_amount");
+        assert!(e.is_synthetic());
+        assert!(e.to_string().ends_with("(automatic)"));
+    }
+
+    #[test]
+    fn recent_record() {
+        use TransactionType::*;
+        let mut r = RecentTransactions::default();
+
+        r.record(Deposit, Some(10.0), None, 3, None);
+        r.record(Dispute, None, None, 3, None);
+        r.record(Withdrawal, Some(20.0), None, 3, None);
+        r.record(Deposit, Some(30.0), None, 3, None);
+
+        let last: Vec<_> = r.last(2).collect();
+        assert_eq!(last, vec![(Deposit,Some(30.0)), (Withdrawal,Some(20.0))]);
+        assert_eq!(r.last(10).count(), 3);
+    }
+
+    #[test]
+    fn recent_since() {
+        use TransactionType::*;
+        let mut r = RecentTransactions::default();
+
+        // transactions within the span are kept beyond the size
+        r.record(Deposit, Some(10.0), Some(1_000), 1, Some(5_000));
+        r.record(Withdrawal, Some(20.0), Some(2_000), 1, Some(5_000));
+        r.record(Deposit, Some(30.0), None, 1, Some(5_000));
+        r.record(Withdrawal, Some(40.0), Some(6_000), 1, Some(5_000));
+
+        let since: Vec<_> = r.since(1_500).collect();
+        assert_eq!(since, vec![(Withdrawal,Some(40.0)), (Withdrawal,Some(20.0))]);
+        assert_eq!(r.since(0).count(), 2);
+        assert_eq!(r.last(10).count(), 3);
+    }
+}
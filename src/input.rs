@@ -0,0 +1,140 @@
+use crate::processor::TransactionProcessorErr;
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Orders multiple input files can be processed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputOrder {
+    /// One after another in the order they're listed
+    #[default]
+    Listed,
+    /// One after another in order of their file name
+    Name,
+    /// Merged by the timestamp column, with a row without a timestamp taking
+    /// that of the row before it in its file
+    Timestamp
+}
+
+impl FromStr for InputOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s.to_lowercase().as_str() {
+            "listed" => Ok(InputOrder::Listed),
+            "name" => Ok(InputOrder::Name),
+            "timestamp" => Ok(InputOrder::Timestamp),
+            _ => Err(format!("unknown input order '{}'", s))
+        }
+    }
+}
+
+/// The input file and line a transaction was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub input: Arc<str>,
+    pub line: u64
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.input, self.line)
+    }
+}
+
+/// Serialize an optional Source as "file:line"
+pub fn serialize_source<S>(data: &Option<Source>, s: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer {
+    match data {
+        Some(source) => s.collect_str(source),
+        None => s.serialize_none()
+    }
+}
+
+/// Expand a list of input paths, globs and directories into input files
+///
+/// A directory gives the `.csv` files in it and a glob the files matching it,
+/// both in order of their path. Any other path is used as it is
+///
+/// Note: A glob or directory without any files is an error
+pub fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>,TransactionProcessorErr> {
+    use TransactionProcessorErr::*;
+
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let path = PathBuf::from(pattern);
+        let mut found: Vec<PathBuf> = if path.is_dir() {
+            fs::read_dir(&path).map_err(IOError)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<PathBuf>,_>>().map_err(IOError)?
+                .into_iter()
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "csv"))
+                .collect()
+        } else if pattern.contains(['*', '?', '[']) {
+            glob::glob(pattern).map_err(|e| InputError(format!("{}: {}", pattern, e)))?
+                .filter_map(Result::ok)
+                .filter(|p| p.is_file())
+                .collect()
+        } else {
+            inputs.push(path);
+            continue;
+        };
+        if found.is_empty() {
+            return Err(InputError(format!("{}: no input files found", pattern)));
+        }
+        found.sort();
+        inputs.append(&mut found);
+    }
+
+    Ok(inputs)
+}
+
+/// Sort input files into the order they're processed in
+///
+/// Note: Files merged by timestamp keep their listed order, which breaks
+/// ties between rows with the same timestamp
+pub fn sort_inputs(inputs: &mut [PathBuf], order: InputOrder) {
+    if order == InputOrder::Name {
+        inputs.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn order_from_str() {
+        assert_eq!("listed".parse(), Ok(InputOrder::Listed));
+        assert_eq!("Name".parse(), Ok(InputOrder::Name));
+        assert_eq!("timestamp".parse(), Ok(InputOrder::Timestamp));
+        assert!("size".parse::<InputOrder>().is_err());
+    }
+
+    #[test]
+    fn expand() {
+        let dir = std::env::temp_dir().join(format!("expand_{}", std::process::id()));
+        fs::create_dir_all(dir.join("b")).unwrap();
+        for file in ["a/2.csv", "a/1.csv", "a/notes.txt", "b/0.csv"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let path = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+        let inputs = expand_inputs(&[path("b/0.csv"), path("a"), path("missing.csv")]).unwrap();
+        assert_eq!(inputs, vec![dir.join("b/0.csv"), dir.join("a/1.csv"), dir.join("a/2.csv"),
+                                dir.join("missing.csv")]);
+        let mut inputs = expand_inputs(&[path("*/*.csv")]).unwrap();
+        assert_eq!(inputs, vec![dir.join("a/1.csv"), dir.join("a/2.csv"), dir.join("b/0.csv")]);
+        sort_inputs(&mut inputs, InputOrder::Name);
+        assert_eq!(inputs, vec![dir.join("b/0.csv"), dir.join("a/1.csv"), dir.join("a/2.csv")]);
+
+        assert!(expand_inputs(&[path("*.json")]).is_err());
+        assert!(expand_inputs(&[path("b/[")]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::client::Client;
+
+use std::fmt;
+
+/// Balances within this of each other are always equal
+const EPSILON: f64 = 0.00005;
+/// Floating point error allowed per unit of an account's turnover
+const RELATIVE: f64 = 1e-12;
+
+/// An invariant of a Client's account that doesn't hold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Violation {
+    /// total isn't available + held
+    Total { available: f64, held: f64, total: f64 },
+    /// held is negative
+    NegativeHeld(f64),
+    /// held isn't the amount held for open disputes and authorizations
+    Held { held: f64, expected: f64 }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Violation::*;
+        match self {
+            Total { available, held, total } => {
+                write!(f, "total {} != available {} + held {}", total, available, held)
+            },
+            NegativeHeld(held) => write!(f, "held {} is negative", held),
+            Held { held, expected } => {
+                write!(f, "held {} != {} held for open disputes and authorizations",
+                       held, expected)
+            }
+        }
+    }
+}
+
+/// Check the invariants of a Client's account
+///
+/// expected_held is the amount the Client should have held for its open
+/// disputes and authorizations. turnover is the total size of the
+/// transactions that moved funds through the account
+///
+/// Note: Floating point error builds up with the amounts moved through an
+/// account, so balances are compared to within a tolerance that grows with
+/// its turnover
+pub fn check_client(client: &Client, expected_held: f64, turnover: f64) -> Vec<Violation> {
+    let (available, held, total) = (client.get_available_funds(),
+                                     client.get_held_funds(),
+                                     client.get_total_funds());
+    let tolerance = EPSILON.max(turnover * RELATIVE);
+
+    let mut violations = Vec::new();
+    if (available + held - total).abs() > tolerance {
+        violations.push(Violation::Total { available, held, total });
+    }
+    if held < -tolerance {
+        violations.push(Violation::NegativeHeld(held));
+    }
+    if (held - expected_held).abs() > tolerance {
+        violations.push(Violation::Held { held, expected: expected_held });
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_client() {
+        let mut c = Client::new(500);
+        c.add_funds(10.0);
+        c.hold_funds(4.0);
+        assert!(super::check_client(&c, 4.0, 14.0).is_empty());
+        assert_eq!(super::check_client(&c, 3.0, 14.0),
+                   vec![Violation::Held { held: 4.0, expected: 3.0 }]);
+        // a large turnover allows for more floating point error
+        assert!(super::check_client(&c, 3.0, 1e13).is_empty());
+
+        c.restore_funds(4.0);
+        c.hold_funds(-1.0);
+        assert_eq!(super::check_client(&c, 0.0, 19.0),
+                   vec![Violation::NegativeHeld(-1.0),
+                        Violation::Held { held: -1.0, expected: 0.0 }]);
+    }
+}
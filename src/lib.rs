@@ -0,0 +1,27 @@
+//! A Transaction Processor that applies CSV formatted transactions to Client
+//! accounts and writes out the resulting account states
+//!
+//! The command line tool is a thin wrapper around `TransactionProcessor`
+
+pub mod client;
+pub mod config;
+pub mod diff;
+pub mod dsl;
+pub mod generate;
+pub mod history;
+pub mod input;
+pub mod parse;
+pub mod invariants;
+pub mod policy;
+pub mod processor;
+#[cfg(test)]
+mod proptests;
+pub mod reconcile;
+pub mod reference;
+pub mod rules;
+pub mod statement;
+pub mod status;
+pub mod time;
+pub mod transaction;
+pub mod types;
+pub mod velocity;
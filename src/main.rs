@@ -1,26 +1,362 @@
-mod client;
-mod processor;
-mod transaction;
-mod types;
-
-use processor::TransactionProcessor;
+use transaction_processor::config::Config;
+use transaction_processor::diff::diff;
+use transaction_processor::dsl::load_rules;
+use transaction_processor::generate::{write_workload,WorkloadConfig};
+use transaction_processor::input::{expand_inputs,InputOrder};
+use transaction_processor::processor::TransactionProcessor;
+use transaction_processor::reconcile::{read_records,reconcile};
+use transaction_processor::statement::StatementFormat;
+use transaction_processor::time::parse_timestamp;
+use transaction_processor::types::*;
 
 use std::env;
 use std::fs::File;
-use std::io;
+use std::io::{self,BufWriter};
+use std::process;
 
-/// A Transaction Processor that's able to read from a CSV file of transactions
+/// A Transaction Processor that's able to read from CSV files of transactions
 /// and write out a CSV list of Client account states after processing the
 /// transactions
+///
+/// Inputs can be files, globs or directories, which give the `.csv` files in
+/// them
+///
+/// Usage:
+///   transaction-processor [options] <input>...
+///   transaction-processor statement [options] [--client <id>]
+///                                   [--format csv|json|text] <input>...
+///   transaction-processor reconcile [options] --expected <file>
+///                                   [--tolerance <amount>] [--from-balances]
+///                                   <input>...
+///   transaction-processor diff [--format text|json] <balances file>
+///                              <balances file>
+///   transaction-processor rules check <rule file>...
+///   transaction-processor generate [--clients <n>] [--rows <n>]
+///                                  [--disputes <rate>] [--resolves <rate>]
+///                                  [--chargebacks <rate>] [--errors <rate>]
+///                                  [--seed <n>] [--expected <file>]
+///                                  [<output file>]
+///
+/// Options:
+///   --allow-admin     process admin operations
+///   --config <file>   load a TOML config file
+///   --client-config <file>
+///                     load a CSV file of per client settings
+///   --opening <file>  load opening balances from a CSV file of Client
+///                     balances
+///   --legacy-output   write the locked column without the status column
+///   --rules <file>    check transactions against the rules in a rule file
+///   --as-of <time>    expire disputes past their deadline at a time
+///   --checked         check account invariants after every transaction
+///   --order listed|name|timestamp
+///                     process inputs one after another in the listed order
+///                     (the default) or by file name, or merge their rows by
+///                     timestamp
 fn main() {
-    if env::args().len() == 1 {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
         panic!("Expected at least 1 arg -- a CSV file path");
     }
 
-    let path = env::args().nth(1).unwrap();
-    let csv_handle = File::open(path).unwrap();
+    match args[0].as_str() {
+        "statement" => statement(Options::parse(&args[1..])),
+        "reconcile" => reconcile_balances(Options::parse(&args[1..])),
+        "diff" => diff_balances(&args[1..]),
+        "generate" => generate(&args[1..]),
+        "rules" if args.get(1).map(String::as_str) == Some("check") => {
+            rules_check(&args[2..])
+        },
+        _ => balances(Options::parse(&args))
+    }
+}
+
+/// Command line options
+struct Options {
+    paths: Vec<String>,
+    order: InputOrder,
+    allow_admin: bool,
+    config: Option<String>,
+    client_config: Option<String>,
+    opening: Option<String>,
+    legacy_output: bool,
+    checked: bool,
+    rules: Vec<String>,
+    as_of: Option<Timestamp>,
+    client: Option<ClientID>,
+    format: StatementFormat,
+    expected: Option<String>,
+    tolerance: f64,
+    from_balances: bool
+}
+
+impl Options {
+    /// Parse command line options from a list of args
+    fn parse(args: &[String]) -> Self {
+        let mut paths = Vec::new();
+        let mut order = InputOrder::default();
+        let mut allow_admin = false;
+        let mut config = None;
+        let mut client_config = None;
+        let mut opening = None;
+        let mut legacy_output = false;
+        let mut checked = false;
+        let mut rules = Vec::new();
+        let mut as_of = None;
+        let mut client = None;
+        let mut format = StatementFormat::Text;
+        let mut expected = None;
+        let mut tolerance: f64 = 0.0;
+        let mut from_balances = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--allow-admin" => allow_admin = true,
+                "--config" => {
+                    let c = args.next().expect("Expected a file path after --config");
+                    config = Some(c.clone());
+                },
+                "--client-config" => {
+                    let c = args.next().expect("Expected a file path after --client-config");
+                    client_config = Some(c.clone());
+                },
+                "--opening" => {
+                    let o = args.next().expect("Expected a file path after --opening");
+                    opening = Some(o.clone());
+                },
+                "--legacy-output" => legacy_output = true,
+                "--checked" => checked = true,
+                "--rules" => {
+                    let r = args.next().expect("Expected a file path after --rules");
+                    rules.push(r.clone());
+                },
+                "--as-of" => {
+                    let t = args.next().expect("Expected a time after --as-of");
+                    as_of = Some(parse_timestamp(t).unwrap());
+                },
+                "--client" => {
+                    let id = args.next().expect("Expected a client ID after --client");
+                    client = Some(id.parse().expect("Invalid client ID"));
+                },
+                "--format" => {
+                    let f = args.next().expect("Expected a format after --format");
+                    format = f.parse().unwrap();
+                },
+                "--expected" => {
+                    let e = args.next().expect("Expected a file path after --expected");
+                    expected = Some(e.clone());
+                },
+                "--tolerance" => {
+                    let t = args.next().expect("Expected an amount after --tolerance");
+                    tolerance = t.parse().expect("Invalid tolerance");
+                    if tolerance.is_nan() || tolerance < 0.0 {
+                        panic!("Expected a non-negative tolerance, not {}", t);
+                    }
+                },
+                "--from-balances" => from_balances = true,
+                "--order" => {
+                    let o = args.next().expect("Expected an order after --order");
+                    order = o.parse().unwrap();
+                },
+                _ => paths.push(arg.clone())
+            }
+        }
+        if paths.is_empty() {
+            panic!("Expected a CSV file path");
+        }
+
+        Self {
+            paths,
+            order,
+            allow_admin,
+            config,
+            client_config,
+            opening,
+            legacy_output,
+            checked,
+            rules,
+            as_of,
+            client,
+            format,
+            expected,
+            tolerance,
+            from_balances
+        }
+    }
+
+    /// Create a TransactionProcessor configured by the options, recording
+    /// the history of every Client if record_history is set
+    fn processor(&self, record_history: bool) -> TransactionProcessor {
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(record_history);
+        tp.set_allow_admin(self.allow_admin);
+        tp.set_legacy_output(self.legacy_output);
+        tp.set_checked(self.checked);
+        if let Some(path) = &self.config {
+            tp.set_config(Config::load(path).unwrap());
+        }
+        // rule files are loaded after the config as it replaces any rules
+        for path in &self.rules {
+            for rule in load_rules(path).unwrap() {
+                tp.add_rule(Box::new(rule));
+            }
+        }
+        if let Some(path) = &self.opening {
+            tp.load_opening_balances(File::open(path).unwrap()).unwrap();
+        }
+        if let Some(path) = &self.client_config {
+            tp.process_client_config_stream(File::open(path).unwrap()).unwrap();
+        }
+        tp
+    }
 
-    let mut tp = TransactionProcessor::new();
-    tp.process_csv_stream(csv_handle).unwrap();
+    /// Process the input files with a TransactionProcessor
+    fn process(&self, tp: &mut TransactionProcessor) {
+        let paths = expand_inputs(&self.paths).unwrap();
+        tp.process_csv_files(&paths, self.order).unwrap();
+        if let Some(now) = self.as_of {
+            tp.sweep_disputes(now);
+        }
+    }
+}
+
+/// Process CSV files of transactions and write out Client balances
+fn balances(opts: Options) {
+    let mut tp = opts.processor(false);
+    opts.process(&mut tp);
     tp.write_csv_to_stream(io::stdout()).unwrap();
 }
+
+/// Process CSV files of transactions and write out the event history of one
+/// or all Clients
+fn statement(opts: Options) {
+    let mut tp = opts.processor(true);
+    opts.process(&mut tp);
+    tp.write_statement_to_stream(opts.client, opts.format, io::stdout()).unwrap();
+}
+
+/// Compare Client balances against a CSV file of expected balances
+///
+/// The inputs are either transactions to process or, with `--from-balances`,
+/// a single file of the balances output by an earlier run. Exits with a
+/// non-zero code if any balances don't match
+fn reconcile_balances(opts: Options) {
+    let path = opts.expected.as_ref().expect("Expected --expected <file>");
+    let expected = read_records(File::open(path).unwrap()).unwrap();
+    let actual = if opts.from_balances {
+        if opts.paths.len() != 1 {
+            panic!("Expected 1 balances file path with --from-balances");
+        }
+        read_records(File::open(&opts.paths[0]).unwrap()).unwrap()
+    } else {
+        let mut tp = opts.processor(false);
+        opts.process(&mut tp);
+        tp.get_records()
+    };
+
+    let mismatches = reconcile(&actual, &expected, opts.tolerance);
+    for m in &mismatches {
+        println!("{}", m);
+    }
+    if !mismatches.is_empty() {
+        process::exit(1);
+    }
+    println!("{} clients match", actual.len());
+}
+
+/// Write out how Client balances changed between two CSV files of balances
+fn diff_balances(args: &[String]) {
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                match args.next().expect("Expected a format after --format").as_str() {
+                    "text" => json = false,
+                    "json" => json = true,
+                    f => panic!("unknown diff format '{}'", f)
+                }
+            },
+            _ => paths.push(arg)
+        }
+    }
+    if paths.len() != 2 {
+        panic!("Expected 2 balances file paths");
+    }
+
+    let before = read_records(File::open(paths[0]).unwrap()).unwrap();
+    let after = read_records(File::open(paths[1]).unwrap()).unwrap();
+    let diffs = diff(&before, &after);
+    if json {
+        serde_json::to_writer_pretty(io::stdout(), &diffs).unwrap();
+        println!();
+    } else {
+        for d in &diffs {
+            println!("{}", d);
+        }
+    }
+}
+
+/// Write a generated CSV file of transactions, to the output file or stdout
+///
+/// With `--expected`, also writes the balances processing it should give
+fn generate(args: &[String]) {
+    let mut config = WorkloadConfig::default();
+    let mut expected = None;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next().unwrap_or_else(|| panic!("Expected a value after {}", name)).clone()
+        };
+        match arg.as_str() {
+            "--clients" => config.clients = value(arg).parse().expect("Invalid client count"),
+            "--rows" => config.rows = value(arg).parse().expect("Invalid row count"),
+            "--disputes" => config.dispute_rate = value(arg).parse().expect("Invalid rate"),
+            "--resolves" => config.resolve_rate = value(arg).parse().expect("Invalid rate"),
+            "--chargebacks" => config.chargeback_rate = value(arg).parse().expect("Invalid rate"),
+            "--errors" => config.error_rate = value(arg).parse().expect("Invalid rate"),
+            "--seed" => config.seed = value(arg).parse().expect("Invalid seed"),
+            "--expected" => expected = Some(value(arg)),
+            _ => path = Some(arg.clone())
+        }
+    }
+    if !config.validate() {
+        panic!("Expected at least 1 client and rates adding up to no more than 1");
+    }
+
+    let model = match &path {
+        Some(path) => write_workload(&config, BufWriter::new(File::create(path).unwrap())),
+        None => write_workload(&config, BufWriter::new(io::stdout().lock()))
+    }.unwrap();
+    if let Some(path) = expected {
+        model.write_csv_to_stream(File::create(path).unwrap()).unwrap();
+    }
+}
+
+/// Check that rule files parse, writing out any errors
+///
+/// Exits with a non-zero code if any file has an error
+fn rules_check(paths: &[String]) {
+    if paths.is_empty() {
+        panic!("Expected at least 1 rule file path");
+    }
+
+    let mut ok = true;
+    for path in paths {
+        match load_rules(path) {
+            Ok(rules) => println!("{}: {} rules ok", path, rules.len()),
+            Err(e) => {
+                eprintln!("{}", e);
+                ok = false;
+            }
+        }
+    }
+
+    if !ok {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,514 @@
+use crate::types::*;
+use crate::time::parse_timestamp;
+use crate::transaction::{Transaction,TransactionType};
+
+use std::collections::VecDeque;
+use std::io::{self,Cursor,SeekFrom};
+use std::str;
+
+use csv::{ByteRecord,Error,Position,Reader,ReaderBuilder,StringRecord,Trim,Writer};
+
+/// Most digits in an amount parsed as fixed point, so that it's exact as an
+/// integer in an f64
+const MAX_DIGITS: usize = 15;
+/// Powers of 10 exact in an f64
+const POWERS_OF_10: [f64; MAX_DIGITS + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15
+];
+
+/// Positions of the transaction fields in the columns of a CSV file, for
+/// parsing rows straight from their bytes
+///
+/// Only parses rows whose fields are in their plain form, e.g. `123` or
+/// `1.5`. Other rows are left to serde, which gives the same transaction or
+/// error as for any other row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    typ: usize,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+    counterparty: Option<usize>,
+    reason: Option<usize>,
+    timestamp: Option<usize>
+}
+
+impl Columns {
+    /// Find the transaction fields in the headers of a CSV file
+    ///
+    /// Returns None if a required field is missing or any field is repeated,
+    /// in which case every row is an error left to serde to report
+    pub fn new(headers: &StringRecord) -> Option<Self> {
+        let (mut typ, mut client, mut tx) = (None, None, None);
+        let (mut amount, mut counterparty, mut reason, mut timestamp) = (None, None, None, None);
+        for (i, header) in headers.iter().enumerate() {
+            let column = match header {
+                "type" => &mut typ,
+                "client" => &mut client,
+                "tx" => &mut tx,
+                "amount" => &mut amount,
+                "counterparty" => &mut counterparty,
+                "reason" => &mut reason,
+                "timestamp" => &mut timestamp,
+                _ => continue
+            };
+            if column.replace(i).is_some() {
+                return None;
+            }
+        }
+
+        Some(Self {
+            typ: typ?,
+            client: client?,
+            tx: tx?,
+            amount,
+            counterparty,
+            reason,
+            timestamp
+        })
+    }
+
+    /// Parse a transaction from an untrimmed CSV row, trimming whitespace
+    /// from its fields
+    ///
+    /// Returns None if any field isn't in its plain form, the row isn't ASCII
+    /// or it has a reason
+    pub fn parse(&self, record: &ByteRecord) -> Option<Transaction> {
+        let field = |i: usize| record.get(i).map(<[u8]>::trim_ascii);
+        let optional = |i: Option<usize>| i.and_then(field).filter(|f| !f.is_empty());
+
+        // serde needs every field to be UTF-8, even ones it ignores
+        if !record.as_slice().is_ascii() {
+            return None;
+        }
+        if optional(self.reason).is_some() {
+            return None;
+        }
+        let amount = match optional(self.amount) {
+            Some(f) => Some(parse_amount(f)?),
+            None => None
+        };
+        let counterparty = match optional(self.counterparty) {
+            Some(f) => Some(parse_int::<ClientID>(f)?),
+            None => None
+        };
+        let timestamp = match optional(self.timestamp) {
+            Some(f) => Some(parse_timestamp(str::from_utf8(f).ok()?).ok()?),
+            None => None
+        };
+
+        Some(Transaction::from_fields(
+            parse_type(field(self.typ)?)?,
+            parse_int(field(self.client)?)?,
+            parse_int(field(self.tx)?)?,
+            amount,
+            counterparty,
+            timestamp
+        ))
+    }
+}
+
+/// Reads transactions from CSV rows, keeping track of the line each was
+/// read from
+///
+/// Rows are parsed by the fast path where possible and otherwise
+/// deserialized with serde, which is used for every row if fast is false
+pub struct Rows<R> {
+    reader: Reader<LineBreaks<R>>,
+    headers: Option<StringRecord>,
+    columns: Option<Columns>,
+    fast: bool,
+    record: ByteRecord,
+    string_record: StringRecord,
+    line: u64
+}
+
+impl<R> Rows<R>
+        where R: io::Read {
+    /// Read the headers of CSV rows to get ready to read the rows
+    pub fn new(reader: R, fast: bool) -> Self {
+        // the fast path trims fields itself as trimming them in the reader
+        //  allocates a new record for every row
+        let trim = if fast { Trim::Headers } else { Trim::All };
+        let mut reader = ReaderBuilder::new()
+            .trim(trim)    // allow leading/trailing whitespace
+            .from_reader(LineBreaks::new(reader));
+        let headers = reader.headers().ok().cloned();
+        let columns = headers.as_ref().filter(|_| fast).and_then(Columns::new);
+        let line = reader.position().line();
+        Self {
+            reader,
+            headers,
+            columns,
+            fast,
+            record: ByteRecord::new(),
+            string_record: StringRecord::new(),
+            line
+        }
+    }
+
+    /// Get the headers, or None if they couldn't be read
+    pub fn headers(&self) -> Option<&StringRecord> {
+        self.headers.as_ref()
+    }
+
+    /// Get the positions of the transaction fields, or None if not using the
+    /// fast path or they aren't all in the headers
+    pub fn columns(&self) -> Option<&Columns> {
+        self.columns.as_ref()
+    }
+
+    /// Get the position of the next row
+    pub fn position(&self) -> &Position {
+        self.reader.position()
+    }
+
+    /// Get the line the last row read started on
+    ///
+    /// Note: If reading a row failed, this is the line reading stopped on
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Read the next transaction, or None if there are no rows left
+    pub fn next_transaction(&mut self) -> Result<Option<Transaction>,Error> {
+        let pos = self.reader.position().clone();
+        // the serde path is the same as the reader's deserialize iterator
+        let read = if self.fast {
+            self.reader.read_byte_record(&mut self.record)
+        } else {
+            self.reader.read_record(&mut self.string_record)
+        };
+        let record = if self.fast { &self.record } else { self.string_record.as_byte_record() };
+        let after = self.reader.position().clone();
+        let (breaks, ends_line) = self.reader.get_ref().breaks(pos.byte(), after.byte());
+        self.line = row_line(&after, breaks, ends_line, record);
+        self.reader.get_mut().forget(after.byte());
+        if !read? {
+            return Ok(None);
+        }
+
+        if !self.fast {
+            return self.string_record.deserialize(self.headers.as_ref()).map(Some);
+        }
+        // every row is read into the same record, and any the fast path
+        //  can't parse is left to serde as it would have been read
+        match self.columns.as_ref().and_then(|c| c.parse(&self.record)) {
+            Some(trans) => Ok(Some(trans)),
+            None => to_string_record(&self.record, &pos)?.deserialize(self.headers.as_ref()).map(Some)
+        }
+    }
+
+    /// Skip a number of rows without parsing them
+    pub fn skip(&mut self, rows: usize) -> Result<(),Error> {
+        for _ in 0..rows {
+            self.reader.read_byte_record(&mut self.record)?;
+            let after = self.reader.position().byte();
+            self.reader.get_mut().forget(after);
+        }
+        Ok(())
+    }
+}
+
+/// Reader keeping the offsets of the line breaks read through it, so the
+/// line a CSV row ends on can be found once it's read
+///
+/// Note: The reader a CSV row is read with only counts the line breaks it
+/// has passed, which for one ending with "\r\n" doesn't include the "\n"
+pub struct LineBreaks<R> {
+    inner: R,
+    read: u64,
+    breaks: VecDeque<u64>
+}
+
+impl<R> LineBreaks<R> {
+    /// Wrap a reader to keep track of its line breaks
+    pub fn new(inner: R) -> Self {
+        Self { inner, read: 0, breaks: VecDeque::new() }
+    }
+
+    /// Count the line breaks between two offsets not yet forgotten, and
+    /// check if the last byte before end is one
+    pub fn breaks(&self, start: u64, end: u64) -> (usize, bool) {
+        let breaks = self.breaks.iter().skip_while(|&&b| b < start).take_while(|&&b| b < end);
+        breaks.fold((0, false), |(n, _), &b| (n + 1, b + 1 == end))
+    }
+
+    /// Forget the line breaks before an offset
+    pub fn forget(&mut self, offset: u64) {
+        while self.breaks.front().is_some_and(|&b| b < offset) {
+            self.breaks.pop_front();
+        }
+    }
+}
+
+impl<R> io::Read for LineBreaks<R>
+        where R: io::Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let read = self.read;
+        self.breaks.extend(buf[..n].iter().enumerate()
+            .filter(|(_, &b)| b == b'\n')
+            .map(|(i, _)| read + i as u64));
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+/// Get the line a CSV row started on from the position after reading it,
+/// the number of line breaks read with it, whether it ended with one, and
+/// the row
+///
+/// Note: The position of the row itself can be before any blank lines
+/// skipped to reach it
+fn row_line(after: &Position, breaks: usize, ends_line: bool, record: &ByteRecord) -> u64 {
+    // only a blank line or a quoted field adds more than the line break
+    //  ending the row
+    let breaks = if breaks > ends_line as usize {
+        record.as_slice().iter().filter(|&&b| b == b'\n').count() + ends_line as usize
+    } else {
+        breaks
+    };
+    after.line().saturating_sub(breaks as u64).max(1)
+}
+
+/// Transactions parsed from a chunk of CSV rows
+#[derive(Debug)]
+pub struct Chunk {
+    /// The transactions with the line each started on, counted from 1 at the
+    /// start of the chunk
+    pub transactions: Vec<(Transaction,u64)>,
+    /// Number of line breaks in the chunk
+    pub lines: u64,
+    /// Whether every row was parsed, or parsing stopped at an error
+    pub complete: bool
+}
+
+/// Split CSV rows into chunks of about a size, each ending at a line break
+///
+/// Note: Rows can only be split this way if no field is quoted
+pub fn split_lines(data: &[u8], size: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = match rest.get(size..).and_then(|r| r.iter().position(|&b| b == b'\n')) {
+            Some(i) => size + i + 1,
+            None => rest.len()
+        };
+        let (chunk, next) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = next;
+    }
+    chunks
+}
+
+/// Parse a chunk of CSV rows without its headers
+///
+/// Parsing stops at the first row with an error, which includes one with a
+/// different number of fields than the headers. The error isn't kept as its
+/// position is only relative to the chunk
+pub fn parse_chunk(chunk: &[u8], headers: &StringRecord, columns: &Columns) -> Chunk {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(chunk);
+    let mut record = ByteRecord::new();
+    let mut transactions = Vec::new();
+    let complete = loop {
+        let start = reader.position().byte();
+        match reader.read_byte_record(&mut record) {
+            Ok(true) if record.len() == headers.len() => {},
+            Ok(true) | Err(_) => break false,
+            Ok(false) => break true
+        }
+        let trans = match columns.parse(&record) {
+            Some(trans) => trans,
+            None => {
+                let parsed = to_string_record(&record, &Position::new())
+                    .and_then(|r| r.deserialize(Some(headers)));
+                match parsed {
+                    Ok(trans) => trans,
+                    Err(_) => break false
+                }
+            }
+        };
+        let (start, end) = (start as usize, reader.position().byte() as usize);
+        let breaks = chunk[start..end].iter().filter(|&&b| b == b'\n').count();
+        let ends_line = chunk[end - 1] == b'\n';
+        transactions.push((trans, row_line(reader.position(), breaks, ends_line, &record)));
+    };
+    let lines = chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+
+    Chunk { transactions, lines, complete }
+}
+
+/// Convert an untrimmed CSV row to the trimmed record a reader trimming
+/// fields would have read, or the error it would have given
+///
+/// pos is the position of the reader before it read the row
+pub fn to_string_record(record: &ByteRecord, pos: &Position) -> Result<StringRecord,Error> {
+    let mut trimmed = record.clone();
+    trimmed.trim();
+    if let Ok(mut string_record) = StringRecord::from_byte_record(trimmed.clone()) {
+        string_record.trim();
+        return Ok(string_record);
+    }
+
+    // the UTF-8 error can only be made by a reader, so read the row again
+    //  from the same position
+    let mut writer = Writer::from_writer(Vec::new());
+    writer.write_byte_record(&trimmed)?;
+    // writing to a Vec can't fail so unwrap shouldn't panic here
+    let row = writer.into_inner().unwrap();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(Cursor::new(row));
+    reader.seek_raw(SeekFrom::Start(0), pos.clone())?;
+    reader.read_record(&mut StringRecord::new())?;
+    unreachable!("a row that isn't UTF-8 read as a StringRecord")
+}
+
+/// Parse a transaction type from its name
+fn parse_type(field: &[u8]) -> Option<TransactionType> {
+    use TransactionType::*;
+    Some(match field {
+        b"deposit" => Deposit,
+        b"withdrawal" => Withdrawal,
+        b"transfer" => Transfer,
+        b"dispute" => Dispute,
+        b"resolve" => Resolve,
+        b"chargeback" => Chargeback,
+        b"authorize" => Authorize,
+        b"capture" => Capture,
+        b"void" => Void,
+        b"unlock" => Unlock,
+        b"freeze" => Freeze,
+        b"close" => Close,
+        b"adjust" => Adjust,
+        b"limit" => Limit,
+        _ => return None
+    })
+}
+
+/// Parse an unsigned integer of decimal digits
+fn parse_int<T>(field: &[u8]) -> Option<T>
+        where T: TryFrom<u64> {
+    if field.is_empty() {
+        return None;
+    }
+    let mut n: u64 = 0;
+    for &b in field {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        n = n.checked_mul(10)?.checked_add((b - b'0') as u64)?;
+    }
+    T::try_from(n).ok()
+}
+
+/// Parse an amount of decimal digits with an optional decimal point
+///
+/// The digits are read as an integer and scaled by a power of 10. Both are
+/// exact in an f64, so the division rounds the same as parsing it as an f64
+fn parse_amount(field: &[u8]) -> Option<f64> {
+    let mut n: u64 = 0;
+    let (mut digits, mut decimals) = (0, None);
+    for &b in field {
+        match b {
+            b'0'..=b'9' => {
+                n = n * 10 + (b - b'0') as u64;
+                digits += 1;
+                decimals = decimals.map(|d| d + 1);
+            },
+            b'.' if decimals.is_none() => decimals = Some(0),
+            _ => return None
+        }
+        if digits > MAX_DIGITS {
+            return None;
+        }
+    }
+    if digits == 0 {
+        return None;
+    }
+
+    Some(n as f64 / POWERS_OF_10[decimals.unwrap_or(0)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let headers = StringRecord::from(vec!["type", "client", "tx", "amount", "note"]);
+        let columns = Columns::new(&headers).unwrap();
+        assert_eq!(columns.amount, Some(3));
+        assert_eq!(columns.timestamp, None);
+
+        assert_eq!(Columns::new(&StringRecord::from(vec!["type", "client", "amount"])), None);
+        assert_eq!(Columns::new(&StringRecord::from(vec!["type", "client", "tx", "tx"])), None);
+    }
+
+    #[test]
+    fn parse() {
+        let headers = StringRecord::from(vec!["type", "client", "tx", "amount", "timestamp",
+                                              "counterparty", "reason"]);
+        let columns = Columns::new(&headers).unwrap();
+        let parse = |row: Vec<&str>| columns.parse(&ByteRecord::from(row));
+
+        let t = parse(vec!["transfer", "1", "2", "3.25", "1700000000", "4", ""]).unwrap();
+        assert_eq!(t.get_type(), TransactionType::Transfer);
+        assert_eq!((t.get_client_id(), t.get_id()), (1, 2));
+        assert_eq!(t.get_amount(), Some(3.25));
+        assert_eq!(t.get_counterparty(), Some(4));
+        assert_eq!(t.get_timestamp(), Some(1_700_000_000_000));
+
+        let t = parse(vec![" dispute", "1 ", " 2 ", " ", "", "\t", ""]).unwrap();
+        assert_eq!((t.get_amount(), t.get_counterparty(), t.get_timestamp()), (None, None, None));
+
+        // left to serde
+        assert!(parse(vec!["Deposit", "1", "2", "1.0", "", "", ""]).is_none());
+        assert!(parse(vec!["deposit", "65536", "2", "1.0", "", "", ""]).is_none());
+        assert!(parse(vec!["deposit", "0x1", "2", "1.0", "", "", ""]).is_none());
+        assert!(parse(vec!["deposit", "1", "2", "1e3", "", "", ""]).is_none());
+        assert!(parse(vec!["adjust", "1", "2", "1.0", "", "", "fee"]).is_none());
+        assert!(parse(vec!["deposit", "1", "2", "1.0", "yesterday", "", ""]).is_none());
+    }
+
+    #[test]
+    fn amounts() {
+        for s in ["0", "1", "1.5", ".5", "5.", "0.0001", "123456.7891", "999999999999999",
+                  "0.00000000000001", "2.675", "0.1", "1000000.0001"] {
+            assert_eq!(parse_amount(s.as_bytes()), Some(s.parse::<f64>().unwrap()), "{}", s);
+        }
+        for s in ["", ".", "1.2.3", "-1", "+1", "1e3", "inf", "1234567890123456"] {
+            assert_eq!(parse_amount(s.as_bytes()), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn split() {
+        let data = b"a,1\nb,2\r\nc,3\nd";
+        assert_eq!(split_lines(data, 1), vec![&b"a,1\n"[..], b"b,2\r\n", b"c,3\n", b"d"]);
+        assert_eq!(split_lines(data, 5), vec![&b"a,1\nb,2\r\n"[..], b"c,3\nd"]);
+        assert_eq!(split_lines(data, 100), vec![&data[..]]);
+        assert!(split_lines(b"", 1).is_empty());
+    }
+
+    #[test]
+    fn chunk() {
+        let headers = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let columns = Columns::new(&headers).unwrap();
+
+        let chunk = parse_chunk(b"deposit,1,1,1.0\n\ndeposit,0x1,2,1e0\n", &headers, &columns);
+        assert!(chunk.complete);
+        assert_eq!(chunk.transactions.iter().map(|t| t.1).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(chunk.lines, 3);
+
+        let chunk = parse_chunk(b"deposit,1,1,1.0\ndeposit,1,2\ndeposit,1,3,1.0\n",
+                                &headers, &columns);
+        assert!(!chunk.complete);
+        assert_eq!(chunk.transactions.len(), 1);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::transaction::TransactionType;
+use crate::types::*;
+
+use serde::Deserialize;
+
+/// How a dispute is handled when it exceeds a Client's available funds
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NegativeBalanceMode {
+    /// Hold the full amount, leaving the available funds negative
+    #[default]
+    Allow,
+    /// Only hold what's available
+    Cap,
+    /// Reject the dispute
+    Reject
+}
+
+/// What happens to a dispute that's still open at its deadline
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryAction {
+    #[default]
+    Resolve,
+    Chargeback
+}
+
+/// Policy for locking accounts after chargebacks and for what locked accounts
+/// may still do
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Number of chargebacks after which an account is locked, 0 to never
+    /// lock an account for chargebacks
+    pub lock_after_chargebacks: u32,
+    /// Allow deposits to locked accounts
+    pub locked_accepts_deposits: bool,
+    /// Allow disputes, resolves and chargebacks on locked accounts so held
+    /// funds are never stranded
+    ///
+    /// Note: Off by default to keep the original behaviour, which leaves
+    /// funds held for open disputes on a locked account until it's unlocked
+    pub locked_disputes_move_funds: bool,
+    /// How disputes exceeding the available funds are handled
+    pub negative_balance: NegativeBalanceMode,
+    /// Seconds after a transaction in which it can be disputed
+    pub dispute_window: Option<f64>,
+    /// Seconds after a dispute is opened in which it must be resolved or
+    /// charged back
+    pub dispute_deadline: Option<f64>,
+    /// What happens to disputes still open at their deadline
+    pub on_dispute_expiry: ExpiryAction
+}
+
+impl Policy {
+    /// Check if an account should be locked after a number of chargebacks
+    pub fn should_lock(&self, chargebacks: u32) -> bool {
+        (self.lock_after_chargebacks > 0) &&
+        (chargebacks >= self.lock_after_chargebacks)
+    }
+
+    /// Get the dispute window in milliseconds
+    pub fn get_dispute_window(&self) -> Option<Timestamp> {
+        self.dispute_window.map(|w| (w * 1000.0).round() as Timestamp)
+    }
+
+    /// Get the dispute deadline in milliseconds
+    pub fn get_dispute_deadline(&self) -> Option<Timestamp> {
+        self.dispute_deadline.map(|d| (d * 1000.0).round() as Timestamp)
+    }
+
+    /// Check if the policy allows a transaction type on a locked account
+    ///
+    /// Note: Types not allowed by the policy may still be allowed by the
+    /// status matrix
+    pub fn allows_locked(&self, typ: TransactionType) -> bool {
+        use TransactionType::*;
+        match typ {
+            Deposit => self.locked_accepts_deposits,
+            Dispute | Resolve | Chargeback => self.locked_disputes_move_funds,
+            _ => false
+        }
+    }
+}
+
+impl Default for Policy {
+    /// Lock accounts on their first chargeback, don't allow anything more on
+    /// them, let disputes leave available funds negative and keep disputes
+    /// open with no time limits
+    fn default() -> Self {
+        Self {
+            lock_after_chargebacks: 1,
+            locked_accepts_deposits: false,
+            locked_disputes_move_funds: false,
+            negative_balance: NegativeBalanceMode::Allow,
+            dispute_window: None,
+            dispute_deadline: None,
+            on_dispute_expiry: ExpiryAction::Resolve
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn should_lock() {
+        let mut p = Policy::default();
+        assert!(p.should_lock(1));
+
+        p.lock_after_chargebacks = 3;
+        assert!(!p.should_lock(2));
+        assert!(p.should_lock(3));
+
+        p.lock_after_chargebacks = 0;
+        assert!(!p.should_lock(10));
+    }
+
+    #[test]
+    fn allows_locked() {
+        let mut p = Policy::default();
+        assert!(!p.allows_locked(Deposit));
+        assert!(!p.allows_locked(Resolve));
+
+        p.locked_accepts_deposits = true;
+        p.locked_disputes_move_funds = true;
+        assert!(p.allows_locked(Deposit));
+        assert!(p.allows_locked(Dispute));
+        assert!(p.allows_locked(Resolve));
+        assert!(p.allows_locked(Chargeback));
+        assert!(!p.allows_locked(Withdrawal));
+    }
+
+    #[test]
+    fn dispute_times() {
+        let mut p = Policy::default();
+        assert_eq!(p.get_dispute_window(), None);
+        assert_eq!(p.get_dispute_deadline(), None);
+
+        p.dispute_window = Some(1.5);
+        p.dispute_deadline = Some(60.0);
+        assert_eq!(p.get_dispute_window(), Some(1_500));
+        assert_eq!(p.get_dispute_deadline(), Some(60_000));
+    }
+}
@@ -1,23 +1,88 @@
 use crate::types::*;
-use crate::client::Client;
-use crate::transaction::Transaction;
+use crate::client::{Client,ClientRecord};
+use crate::config::{ClientConfig,Config};
+use crate::history::{RejectReason,TransactionInfo};
+use crate::input::{sort_inputs,InputOrder,Source};
+use crate::invariants;
+use crate::parse::{parse_chunk,split_lines,Chunk,Rows};
+use crate::policy::{ExpiryAction,NegativeBalanceMode,Policy};
+use crate::rules::{Rule,Verdict};
+use crate::statement::{self,StatementFormat};
+use crate::transaction::{Transaction,TransactionType};
+use crate::velocity;
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap,BTreeSet,BinaryHeap,HashMap,HashSet};
+use std::fmt;
+use std::fs::File;
 use std::io;
+use std::path::{Path,PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self,Receiver};
+use std::thread;
 
 use csv::{Error,ReaderBuilder,Trim};
+use memmap2::Mmap;
+
+/// Size of the chunks a memory mapped file is split into to parse in parallel
+const CHUNK_SIZE: usize = 1 << 20;
 
 /// The main struct of the Transaction Processor
 pub struct TransactionProcessor {
     clients: HashMap<ClientID,Client>,
-    transactions: HashMap<TransactionID,Transaction>
+    transactions: HashMap<TransactionID,Transaction>,
+    config: Config,
+    rules: Vec<Box<dyn Rule>>,
+    pending: BTreeMap<(Timestamp,u64),Transaction>,
+    received: u64,
+    newest: Option<Timestamp>,
+    deadlines: BTreeSet<(Timestamp,TransactionID)>,
+    record_history: bool,
+    allow_admin: bool,
+    legacy_output: bool,
+    checked: bool,
+    fast_csv: bool,
+    expected_held: HashMap<ClientID,(f64,f64)>
 }
 
 /// Transaction Processor Error
 #[derive(Debug)]
 pub enum TransactionProcessorErr {
     CSVError(Error),
-    TransactionValidateError(String)
+    TransactionValidateError(String),
+    JSONError(serde_json::Error),
+    IOError(io::Error),
+    ClientNotFound(ClientID),
+    ClientConfigValidateError(String),
+    ClientRecordValidateError(String),
+    InputError(String),
+    /// An error attributed to the input file, and line if known, it came from
+    SourceError(String,Box<TransactionProcessorErr>)
+}
+
+impl fmt::Display for TransactionProcessorErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use TransactionProcessorErr::*;
+        match self {
+            CSVError(e) => write!(f, "CSV error: {}", e),
+            TransactionValidateError(t) => write!(f, "invalid transaction: {}", t),
+            JSONError(e) => write!(f, "JSON error: {}", e),
+            IOError(e) => write!(f, "IO error: {}", e),
+            ClientNotFound(id) => write!(f, "client {} not found", id),
+            ClientConfigValidateError(c) => write!(f, "invalid client config: {}", c),
+            ClientRecordValidateError(c) => write!(f, "invalid opening balance: {}", c),
+            InputError(i) => write!(f, "invalid input: {}", i),
+            SourceError(source, e) => write!(f, "{}: {}", source, e)
+        }
+    }
+}
+
+impl std::error::Error for TransactionProcessorErr {}
+
+impl Default for TransactionProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TransactionProcessor {
@@ -25,323 +90,3045 @@ impl TransactionProcessor {
     pub fn new() -> Self {
         Self {
             clients: HashMap::new(),
-            transactions: HashMap::new()
+            transactions: HashMap::new(),
+            config: Config::default(),
+            rules: Vec::new(),
+            pending: BTreeMap::new(),
+            received: 0,
+            newest: None,
+            deadlines: BTreeSet::new(),
+            record_history: false,
+            allow_admin: false,
+            legacy_output: false,
+            checked: false,
+            fast_csv: true,
+            expected_held: HashMap::new()
         }
     }
 
-    /// Process a list of CSV formatted transactions
-    pub fn process_csv_stream<R>(&mut self, reader: R) -> Result<(),TransactionProcessorErr>
+    /// Set the Config used to decide how transactions are applied
+    ///
+    /// Note: This replaces any rules with the ones listed in the Config
+    pub fn set_config(&mut self, config: Config) {
+        self.rules = config.rules.iter().map(|r| r.build()).collect();
+        self.config = config;
+    }
+
+    /// Add a Rule to be checked after any already added
+    pub fn add_rule(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    /// Write Client info in the legacy CSV format
+    ///
+    /// The legacy format has a locked column but no status column
+    pub fn set_legacy_output(&mut self, legacy: bool) {
+        self.legacy_output = legacy;
+    }
+
+    /// Record an event history for every Client, including those already
+    /// created
+    ///
+    /// Note: Turning it off discards any recorded histories
+    pub fn set_record_history(&mut self, enabled: bool) {
+        self.record_history = enabled;
+        for client in self.clients.values_mut() {
+            if enabled {
+                client.enable_history();
+            } else {
+                client.disable_history();
+            }
+        }
+    }
+
+    /// Check the invariants of every affected account after each transaction
+    ///
+    /// A broken invariant panics in debug builds and is reported to stderr,
+    /// along with the transaction and account state, otherwise
+    ///
+    /// Note: This checks every transaction so slows processing down
+    pub fn set_checked(&mut self, enabled: bool) {
+        if enabled && !self.checked {
+            self.rebuild_expected_held();
+        } else if !enabled {
+            self.expected_held.clear();
+        }
+        self.checked = enabled;
+    }
+
+    /// Parse CSV rows straight from their bytes where possible, on by default
+    ///
+    /// Rows are otherwise deserialized with serde. Both give the same
+    /// transactions and errors
+    pub fn set_fast_csv(&mut self, enabled: bool) {
+        self.fast_csv = enabled;
+    }
+
+    /// Allow admin operations (unlock, freeze, close, adjust and limit) to be
+    /// processed
+    ///
+    /// Admin operations are rejected unless this is set
+    pub fn set_allow_admin(&mut self, allowed: bool) {
+        self.allow_admin = allowed;
+    }
+
+    /// Process a list of CSV formatted Client configs
+    ///
+    /// Note: A client will be created for each config if one does not already
+    /// exist
+    pub fn process_client_config_stream<R>(&mut self, reader: R)
+            -> Result<(),TransactionProcessorErr>
+            where R: io::Read {
+        use TransactionProcessorErr::*;
+
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)    // allow leading/trailing whitespace
+            .from_reader(reader);
+        for raw_config in csv_reader.deserialize() {
+            let config: ClientConfig = raw_config.map_err(CSVError)?;
+            if !config.validate() {
+                return Err(ClientConfigValidateError(format!("{:?}",config)));
+            }
+            self.apply_client_config(&config);
+        }
+
+        Ok(())
+    }
+
+    /// Load Clients' opening account states from CSV in the format written by
+    /// `write_csv_to_stream`
+    ///
+    /// Note: A client will be created for each record if one does not already
+    /// exist. Each client may only appear once, and can't have held funds as
+    /// there'd be no dispute or authorization to release them
+    pub fn load_opening_balances<R>(&mut self, reader: R)
+            -> Result<(),TransactionProcessorErr>
             where R: io::Read {
         use TransactionProcessorErr::*;
 
+        let mut loaded = HashSet::new();
         let mut csv_reader = ReaderBuilder::new()
             .trim(Trim::All)    // allow leading/trailing whitespace
             .from_reader(reader);
-        for raw_trans in csv_reader.deserialize() {
-            let trans: Transaction = raw_trans.map_err(|e| CSVError(e))?;
-            // validate transaction since it's possible an invalid one
-            //  was formed
-            if !trans.validate() {
-                return Err(TransactionValidateError(format!("{:?}",trans)));
+        for raw_record in csv_reader.deserialize() {
+            let record: ClientRecord = raw_record.map_err(CSVError)?;
+            if !record.validate() || (record.held != 0.0) || !loaded.insert(record.client) {
+                return Err(ClientRecordValidateError(format!("{:?}",record)));
+            }
+            self.add_client(record.client);
+            // the client was just added so unwrap shouldn't panic here
+            self.clients.get_mut(&record.client).unwrap().load_record(&record);
+            if self.checked {
+                self.expected_held.insert(record.client, (0.0, 0.0));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the settings of a Client config to its Client
+    ///
+    /// Note: A client will be created if one does not already exist
+    pub fn apply_client_config(&mut self, config: &ClientConfig) {
+        self.add_client(config.client);
+        // the client was just added so unwrap shouldn't panic here
+        let client = self.clients.get_mut(&config.client).unwrap();
+        if let Some(limit) = config.overdraft_limit {
+            client.set_overdraft_limit(limit);
+        }
+        if config.tier.is_some() {
+            client.set_tier(config.tier.clone());
+        }
+    }
+
+    /// Process a list of CSV formatted transactions
+    pub fn process_csv_stream<R>(&mut self, reader: R) -> Result<(),TransactionProcessorErr>
+            where R: io::Read {
+        let mut rows = self.csv_rows(reader);
+        self.process_csv_rows(&mut rows, None)?;
+        self.flush_transactions();
+
+        Ok(())
+    }
+
+    /// Process a CSV file of transactions
+    ///
+    /// Regular files are memory mapped, split into chunks at line breaks and
+    /// parsed in parallel, then processed in their original order. Anything
+    /// else, like a pipe, is read as a stream
+    ///
+    /// Note: Transactions and errors are attributed to the file and line
+    /// they were read from
+    pub fn process_csv_file<P>(&mut self, path: P) -> Result<(),TransactionProcessorErr>
+            where P: AsRef<Path> {
+        self.process_csv_files(&[path], InputOrder::Listed)
+    }
+
+    /// Process several CSV files of transactions in an order
+    ///
+    /// Files are processed one after another in the listed order or in order
+    /// of their file name, or have their rows merged by timestamp. Files
+    /// merged by timestamp are read as streams
+    ///
+    /// Note: Transactions and errors are attributed to the file and line
+    /// they were read from
+    pub fn process_csv_files<P>(&mut self, paths: &[P], order: InputOrder)
+            -> Result<(),TransactionProcessorErr>
+            where P: AsRef<Path> {
+        let mut paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        sort_inputs(&mut paths, order);
+        let inputs: Vec<Arc<str>> = paths.iter().map(|p| p.to_string_lossy().into()).collect();
+
+        if order == InputOrder::Timestamp {
+            self.merge_csv_files(&paths, &inputs)?;
+        } else {
+            for (path, input) in paths.iter().zip(&inputs) {
+                self.process_csv_input(path, input)?;
+            }
+        }
+        self.flush_transactions();
+
+        Ok(())
+    }
+
+    /// Process a CSV file of transactions without flushing queued ones
+    fn process_csv_input(&mut self, path: &Path, input: &Arc<str>)
+            -> Result<(),TransactionProcessorErr> {
+        use TransactionProcessorErr::*;
+
+        let file = File::open(path).map_err(|e| SourceError(input.to_string(), Box::new(IOError(e))))?;
+        if !file.metadata().is_ok_and(|m| m.is_file()) {
+            let mut rows = self.csv_rows(file);
+            return self.process_csv_rows(&mut rows, Some(input));
+        }
+        // SAFETY: the file changing while it's mapped can't cause undefined
+        //  behaviour here as it's only read as bytes, though the result is
+        //  as unreliable as reading a file while it's written
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => {
+                let threads = thread::available_parallelism().map_or(1, |n| n.get());
+                self.process_csv_bytes(&map, CHUNK_SIZE, threads, Some(input))
+            },
+            Err(_) => {
+                let mut rows = self.csv_rows(file);
+                self.process_csv_rows(&mut rows, Some(input))
+            }
+        }
+    }
+
+    /// Process CSV files of transactions with their rows merged by timestamp
+    ///
+    /// The next row processed is always the earliest at the head of a file,
+    /// with a row without a timestamp taking that of the row before it in its
+    /// file. Ties go to the file listed first
+    ///
+    /// Note: An error is returned when the row it's in would be processed
+    fn merge_csv_files(&mut self, paths: &[PathBuf], inputs: &[Arc<str>])
+            -> Result<(),TransactionProcessorErr> {
+        use TransactionProcessorErr::*;
+
+        let mut files = Vec::new();
+        for (path, input) in paths.iter().zip(inputs) {
+            let file = File::open(path).map_err(|e| SourceError(input.to_string(), Box::new(IOError(e))))?;
+            files.push(self.csv_rows(file));
+        }
+
+        // each file has its next row waiting in heads, and is in the heap
+        //  keyed by that row's timestamp until it runs out
+        let mut last: Vec<Option<Timestamp>> = vec![None; files.len()];
+        let mut heads = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (i, rows) in files.iter_mut().enumerate() {
+            let head = rows.next_transaction().transpose();
+            if let Some(key) = merge_key(&head, last[i]) {
+                heap.push(Reverse((key, i)));
+            }
+            heads.push(head);
+        }
+
+        while let Some(Reverse((_, i))) = heap.pop() {
+            let input = Some(&inputs[i]);
+            let trans = match heads[i].take() {
+                Some(Ok(trans)) => trans,
+                Some(Err(e)) => {
+                    return Err(source_error(input, files[i].line(), CSVError(e)));
+                },
+                None => unreachable!("only files with a row waiting are in the heap")
+            };
+            last[i] = trans.get_timestamp().or(last[i]);
+            self.submit_csv_transaction(trans, input.map(|input| Source {
+                input: input.clone(),
+                line: files[i].line()
+            }))?;
+
+            heads[i] = files[i].next_transaction().transpose();
+            if let Some(key) = merge_key(&heads[i], last[i]) {
+                heap.push(Reverse((key, i)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Process CSV formatted transactions read from an input, parsing chunks
+    /// of rows on a number of threads
+    ///
+    /// Note: Gives the same result and error as reading the rows as a
+    /// stream, which is done instead when they can't be split at line breaks.
+    /// Queued transactions aren't flushed
+    pub(crate) fn process_csv_bytes(&mut self, data: &[u8], chunk_size: usize, threads: usize,
+                                    input: Option<&Arc<str>>)
+            -> Result<(),TransactionProcessorErr> {
+        use TransactionProcessorErr::*;
+
+        let mut rows = self.csv_rows(data);
+        // quoted fields can span lines
+        if !self.fast_csv || (threads < 2) || data.contains(&b'"') {
+            return self.process_csv_rows(&mut rows, input);
+        }
+        let (headers, columns) = match (rows.headers(), rows.columns()) {
+            (Some(headers), Some(columns)) => (headers.clone(), columns.clone()),
+            _ => return self.process_csv_rows(&mut rows, input)
+        };
+        let start = rows.position().byte() as usize;
+        let chunks = split_lines(&data[start..], chunk_size);
+
+        // each thread parses every threads-th chunk so they're received in
+        //  order, and stops once its chunks are no longer wanted
+        let mut parsed = 0;
+        let mut line = rows.position().line();
+        let complete = thread::scope(|s| {
+            let receivers: Vec<Receiver<Chunk>> = (0..threads)
+                .map(|t| {
+                    let (sender, receiver) = mpsc::sync_channel(1);
+                    let (chunks, headers, columns) = (&chunks, &headers, &columns);
+                    s.spawn(move || {
+                        for chunk in chunks.iter().skip(t).step_by(threads) {
+                            if sender.send(parse_chunk(chunk, headers, columns)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    receiver
+                })
+                .collect();
+
+            for i in 0..chunks.len() {
+                // a thread only stops early if it panicked
+                let chunk = receivers[i % threads].recv().expect("CSV parsing thread panicked");
+                if !chunk.complete {
+                    return Ok(false);
+                }
+                parsed += chunk.transactions.len();
+                for (trans, row) in chunk.transactions {
+                    self.submit_csv_transaction(trans, input.map(|input| Source {
+                        input: input.clone(),
+                        line: line + row - 1
+                    }))?;
+                }
+                line += chunk.lines;
+            }
+            Ok(true)
+        })?;
+
+        // read a chunk with an error in order from its start so the error
+        //  is reported as it would be from a stream
+        if !complete {
+            rows.skip(parsed).map_err(|e| source_error(input, rows.line(), CSVError(e)))?;
+            self.process_csv_rows(&mut rows, input)?;
+        }
+
+        Ok(())
+    }
+
+    /// Create a reader of the transactions in CSV rows
+    fn csv_rows<R>(&self, reader: R) -> Rows<R>
+            where R: io::Read {
+        Rows::new(reader, self.fast_csv)
+    }
+
+    /// Validate and submit every remaining row of a CSV reader, attributing
+    /// them and any error to an input
+    fn process_csv_rows<R>(&mut self, rows: &mut Rows<R>, input: Option<&Arc<str>>)
+            -> Result<(),TransactionProcessorErr>
+            where R: io::Read {
+        use TransactionProcessorErr::*;
+
+        loop {
+            let trans = match rows.next_transaction() {
+                Ok(Some(trans)) => trans,
+                Ok(None) => break,
+                Err(e) => return Err(source_error(input, rows.line(), CSVError(e)))
+            };
+            self.submit_csv_transaction(trans, input.map(|input| Source {
+                input: input.clone(),
+                line: rows.line()
+            }))?;
+        }
+
+        Ok(())
+    }
+
+    /// Validate and submit a transaction read from CSV, along with the input
+    /// and line it was read from
+    fn submit_csv_transaction(&mut self, mut trans: Transaction, source: Option<Source>)
+            -> Result<(),TransactionProcessorErr> {
+        use TransactionProcessorErr::*;
+
+        // validate transaction since it's possible an invalid one
+        //  was formed
+        if !trans.validate() {
+            let err = TransactionValidateError(format!("{:?}",trans));
+            return Err(match source {
+                Some(source) => SourceError(source.to_string(), Box::new(err)),
+                None => err
+            });
+        }
+        trans.set_source(source);
+        self.submit_transaction(trans);
+
+        Ok(())
+    }
+
+    /// Queue a transaction to be processed in timestamp order
+    ///
+    /// A transaction is held back until one with a timestamp later than its
+    /// own by the reorder tolerance is submitted, or until the queue is
+    /// flushed. Transactions without a timestamp are queued behind the
+    /// latest one submitted
+    ///
+    /// Note: Transactions are processed straight away if the tolerance is 0
+    pub fn submit_transaction(&mut self, trans: Transaction) {
+        let tolerance = self.config.timestamps.get_tolerance();
+        let timestamp = match trans.get_timestamp().or(self.newest) {
+            Some(ts) if tolerance > 0 => ts,
+            _ => return self.process_transaction(trans)
+        };
+
+        let newest = self.newest.map_or(timestamp, |n| n.max(timestamp));
+        self.newest = Some(newest);
+        self.pending.insert((timestamp, self.received), trans);
+        self.received += 1;
+
+        // process transactions that can't be preceded by any still to come
+        while self.pending.first_key_value().is_some_and(|(k, _)| k.0 <= newest.saturating_sub(tolerance)) {
+            // checked above so unwrap shouldn't panic here
+            let (_, trans) = self.pending.pop_first().unwrap();
+            self.process_transaction(trans);
+        }
+    }
+
+    /// Process every queued transaction in timestamp order
+    pub fn flush_transactions(&mut self) {
+        while let Some((_, trans)) = self.pending.pop_first() {
+            self.process_transaction(trans);
+        }
+    }
+
+    /// Process a single transaction
+    ///
+    /// Note: A client will be created if one does not already exist
+    pub fn process_transaction(&mut self, trans: Transaction) {
+        use crate::transaction::TransactionType::*;
+
+        let (t_id, t_type) = (trans.get_id(), trans.get_type());
+        let (c_id, t_amount) = (trans.get_client_id(), trans.get_amount());
+        let (t_time, synthetic) = (trans.get_timestamp(), trans.is_synthetic());
+        let info = TransactionInfo::new(&trans);
+        let checked = self.checked.then(|| format!("{:?}", trans));
+
+        // expire any disputes whose deadline passed before this transaction
+        if let (Some(ts), Some(&(deadline, _))) = (t_time, self.deadlines.first()) {
+            if ts > deadline {
+                self.sweep_disputes(ts);
+            }
+        }
+
+        // a transfer, or a dispute of one, also affects the account on the
+        //  other side of the transfer
+        let other_id = match t_type {
+            Transfer => trans.get_counterparty(),
+            Dispute | Resolve | Chargeback => {
+                self.transactions.get(&t_id).and_then(|t| t.get_other_party(c_id))
+            },
+            _ => None
+        };
+
+        // add clients if they don't exist
+        self.add_client(c_id);
+        if let Some(o_id) = other_id {
+            self.add_client(o_id);
+        }
+
+        // the status of every affected account must allow the transaction,
+        //  except for the outcome of an expired dispute which always applies.
+        //  The receiving side of a transfer only needs to allow deposits
+        let other_type = if t_type == Transfer { Deposit } else { t_type };
+        let permitted = if trans.is_synthetic() {
+            Ok(())
+        } else {
+            self.permits(c_id, t_type)
+                .and_then(|_| other_id.map_or(Ok(()), |o_id| self.permits(o_id, other_type)))
+        };
+        // every affected account's transactions must be in timestamp order,
+        //  except for the outcome of an expired dispute which is timestamped
+        //  at its deadline and doesn't move the accounts' clocks
+        let in_order = if trans.is_synthetic() {
+            Ok(())
+        } else {
+            self.check_timestamp(c_id, t_time)
+                .and_then(|_| other_id.map_or(Ok(()), |o_id| self.check_timestamp(o_id, t_time)))
+        };
+
+        let held_before = self.checked
+            .then(|| self.transactions.get(&t_id).map(|t| t.get_held_amount()));
+
+        // transactions built outside process_csv_stream haven't been
+        //  validated yet
+        let result = if !trans.validate() {
+            Err(RejectReason::InvalidTransaction)
+        } else if !trans.is_reference() && self.transactions.contains_key(&t_id) {
+            Err(RejectReason::DuplicateTransaction)
+        } else if let Err(reason) = in_order {
+            Err(reason)
+        } else if trans.is_admin() && !self.allow_admin {
+            Err(RejectReason::AdminNotAllowed)
+        } else if let Err(reason) = permitted {
+            Err(reason)
+        } else if let Err(reason) = self.check_velocity(&trans) {
+            Err(reason)
+        } else if let Err(reason) = self.check_rules(&trans, other_id) {
+            Err(reason)
+        } else {
+            match other_id {
+                Some(o_id) => self.process_two_party(trans, o_id),
+                None => self.process_one_party(trans)
+            }
+        };
+
+        if let Some(held_before) = held_before {
+            self.track_expected_held(t_id, held_before);
+        }
+
+        // only an applied transaction moves the clocks of the accounts it
+        //  affected forward
+        if let (Ok(()), Some(ts), false) = (result, t_time, synthetic) {
+            for id in [Some(c_id), other_id].into_iter().flatten() {
+                self.clients.get_mut(&id).unwrap().set_last_timestamp(ts);
+            }
+        }
+
+        // applied transactions are kept as the client's recent transactions
+        //  for velocity limits and rules
+        if result.is_ok() {
+            let client = self.clients.get_mut(&c_id).unwrap();
+            let limits = self.config.velocity_limits(client.get_tier());
+            let size = self.rules.iter().map(|r| r.window())
+                .chain([limits.window])
+                .max().unwrap_or(0);
+            client.record_recent(t_type, t_amount, t_time, size, limits.get_time_window());
+        }
+
+        // clients were added above so unwrap shouldn't panic here
+        self.clients.get_mut(&c_id).unwrap().record_event(&info, result);
+        if let Some(o_id) = other_id {
+            self.clients.get_mut(&o_id).unwrap().record_event(&info, result);
+        }
+
+        // keep the deadline of a newly opened dispute
+        if (t_type == Dispute) && result.is_ok() {
+            let disputed_at = self.transactions.get(&t_id).and_then(|t| t.get_disputed_at());
+            if let (Some(at), Some(deadline)) =
+                    (disputed_at, self.config.policy.get_dispute_deadline()) {
+                self.deadlines.insert((at.saturating_add(deadline), t_id));
+            }
+        }
+
+        if let Some(trans) = checked {
+            self.check_invariants(&trans, [Some(c_id), other_id].into_iter().flatten());
+        }
+    }
+
+    /// Check the invariants of Clients' accounts after a transaction
+    ///
+    /// Panics in debug builds if one doesn't hold, otherwise writes the
+    /// broken invariants and the account state to stderr
+    fn check_invariants<I>(&self, trans: &str, ids: I)
+            where I: Iterator<Item = ClientID> {
+        for id in ids {
+            // clients were added by process_transaction so unwrap shouldn't
+            //  panic here
+            let client = self.clients.get(&id).unwrap();
+            let (expected_held, turnover) = self.get_expected_held(id);
+            let violations = invariants::check_client(client, expected_held, turnover);
+            if violations.is_empty() {
+                continue;
             }
+
+            let violations: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+            let report = format!("invariant broken for client {}: {}\n  transaction: {}\n  \
+                                  account: {:?}",
+                                 id, violations.join(", "), trans, client.to_record(false));
+            if cfg!(debug_assertions) {
+                panic!("{}", report);
+            }
+            eprintln!("{}", report);
+        }
+    }
+
+    /// Work out the amount every Client should have held, and its turnover,
+    /// from every transaction so far
+    ///
+    /// Note: This is only needed when checking is turned on, after which the
+    /// amounts are kept up to date as transactions are processed
+    fn rebuild_expected_held(&mut self) {
+        self.expected_held.clear();
+        for t in self.transactions.values() {
+            let (holder, client) = (t.get_holder(), t.get_client_id());
+            let size = t.get_amount().unwrap_or(0.0).abs();
+            let expected = self.expected_held.entry(holder).or_default();
+            expected.0 += t.get_held_amount();
+            expected.1 += size;
+            if client != holder {
+                self.expected_held.entry(client).or_default().1 += size;
+            }
+        }
+    }
+
+    /// Update the running amount the holder of a transaction should have
+    /// held after processing it, given the amount it held before
+    ///
+    /// A newly stored transaction also adds its size to the turnover of every
+    /// Client it's a party to
+    fn track_expected_held(&mut self, t_id: TransactionID, held_before: Option<f64>) {
+        let t = match self.transactions.get(&t_id) {
+            Some(t) => t,
+            None => return
+        };
+
+        let (holder, client) = (t.get_holder(), t.get_client_id());
+        let expected = self.expected_held.entry(holder).or_default();
+        expected.0 += t.get_held_amount() - held_before.unwrap_or(0.0);
+        if held_before.is_none() {
+            let size = t.get_amount().unwrap_or(0.0).abs();
+            expected.1 += size;
+            if client != holder {
+                self.expected_held.entry(client).or_default().1 += size;
+            }
+        }
+    }
+
+    /// Get the amount a Client should have held for its open disputes and
+    /// authorizations, along with the Client's turnover
+    ///
+    /// The turnover is the total size of every transaction the Client is a
+    /// party to
+    fn get_expected_held(&self, id: ClientID) -> (f64,f64) {
+        self.expected_held.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Resolve or charge back, as the policy says, every dispute still open
+    /// after its deadline
+    ///
+    /// Each expired dispute is processed as a synthetic resolve or chargeback
+    /// timestamped at its deadline
+    ///
+    /// Note: Like admin operations, these apply whatever the status of the
+    /// accounts and aren't checked against the rules. A dispute whose outcome
+    /// is still rejected stays open and is retried by the next sweep
+    pub fn sweep_disputes(&mut self, now: Timestamp) {
+        let deadline = match self.config.policy.get_dispute_deadline() {
+            Some(deadline) => deadline,
+            None => return
+        };
+
+        // deadlines before now, in order
+        let pending = self.deadlines.split_off(&(now, 0));
+        let expired = std::mem::replace(&mut self.deadlines, pending);
+
+        let mut failed = Vec::new();
+        for (expiry, t_id) in expired {
+            // skip disputes closed, or closed and opened again, since
+            //  the deadline was kept
+            let c_id = match self.transactions.get(&t_id) {
+                Some(t) if t.get_disputed_at().map(|at| at.saturating_add(deadline)) ==
+                           Some(expiry) => t.get_client_id(),
+                _ => continue
+            };
+
+            let mut trans = match self.config.policy.on_dispute_expiry {
+                ExpiryAction::Resolve => Transaction::new_resolve(c_id, t_id),
+                ExpiryAction::Chargeback => Transaction::new_chargeback(c_id, t_id)
+            };
+            trans.set_timestamp(Some(expiry));
+            trans.set_synthetic();
             self.process_transaction(trans);
+
+            // the transaction was found above so unwrap shouldn't panic here
+            if self.transactions.get(&t_id).unwrap().get_disputed_at().is_some() {
+                failed.push((expiry, t_id));
+            }
+        }
+        self.deadlines.extend(failed);
+    }
+
+    /// Check that a transaction isn't older than the latest one for a Client
+    ///
+    /// Note: Transactions with the same timestamp are in order
+    fn check_timestamp(&self, id: ClientID, timestamp: Option<Timestamp>)
+            -> Result<(),RejectReason> {
+        let last = self.clients.get(&id).and_then(|c| c.get_last_timestamp());
+        match (last, timestamp) {
+            (Some(last), Some(ts)) if ts < last => Err(RejectReason::OutOfOrder),
+            _ => Ok(())
         }
+    }
+
+    /// Check if a withdrawal, transfer or authorization stays within the
+    /// velocity limits of its Client
+    ///
+    /// The Client's account is frozen on a breach if the limits say so
+    fn check_velocity(&mut self, trans: &Transaction) -> Result<(),RejectReason> {
+        if !velocity::is_outbound(trans.get_type()) {
+            return Ok(());
+        }
+
+        // the client was added by process_transaction so unwrap shouldn't
+        //  panic here
+        let client = self.clients.get_mut(&trans.get_client_id()).unwrap();
+        let limits = self.config.velocity_limits(client.get_tier());
+        let result = limits.check(client.get_recent(),
+                                  trans.get_amount().unwrap(),
+                                  trans.get_timestamp());
+        if result.is_err() && limits.freeze {
+            client.freeze();
+        }
+
+        result
+    }
+
+    /// Check a transaction against every Rule in order, for its Client and
+    /// then for the other party to it if there is one
+    ///
+    /// The names of matching rules are added to the Client they matched for
+    /// as flags and the first rejection is returned
+    ///
+    /// Note: A rule passing the transaction stops any later rules being checked
+    /// for that Client
+    fn check_rules(&mut self, trans: &Transaction, other_id: Option<ClientID>)
+            -> Result<(),RejectReason> {
+        if trans.is_admin() || trans.is_synthetic() {
+            return Ok(());
+        }
+
+        for id in [Some(trans.get_client_id()), other_id].into_iter().flatten() {
+            // clients were added by process_transaction so unwrap shouldn't
+            //  panic here
+            let client = self.clients.get_mut(&id).unwrap();
+            for rule in &self.rules {
+                match rule.check(trans, client) {
+                    Verdict::Allow => (),
+                    Verdict::Pass => break,
+                    Verdict::Flag => client.add_flag(rule.name()),
+                    Verdict::Reject(reason) => {
+                        client.add_flag(rule.name());
+                        return Err(reason);
+                    },
+                    Verdict::Lock(reason) => {
+                        client.add_flag(rule.name());
+                        client.lock();
+                        return Err(reason);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check if the status of a Client's account allows a transaction type
+    fn permits(&self, id: ClientID, typ: TransactionType) -> Result<(),RejectReason> {
+        let status = self.clients.get(&id).map(|c| c.get_status()).unwrap_or_default();
+        if self.config.allows(status, typ) {
+            Ok(())
+        } else {
+            Err(status.reject_reason())
+        }
+    }
+
+    /// Add a Client if one does not already exist
+    fn add_client(&mut self, id: ClientID) {
+        if !self.clients.contains_key(&id) {
+            let mut client = Client::new(id);
+            if self.record_history {
+                client.enable_history();
+            }
+            self.clients.insert(id, client);
+        }
+    }
+
+    /// Process a transaction that only affects the account of its own Client
+    fn process_one_party(&mut self, trans: Transaction) -> Result<(),RejectReason> {
+        // the client was added by process_transaction so unwrap shouldn't
+        //  panic here
+        let client = self.clients.get_mut(&trans.get_client_id()).unwrap();
+        let t_id = trans.get_id();
+
+        // within this match calls to get_amount are unwraped because we know
+        //  at those times that it is Some bacause of where the transaction
+        //  came from or what type of transaction it is
+        use crate::transaction::TransactionType::*;
+        match trans.get_type() {
+            // add funds to client and record transaction
+            Deposit => {
+                client.add_funds(trans.get_amount().unwrap());
+                self.transactions.insert(t_id, trans);
+                Ok(())
+            },
+            // remove funds from client and record transaction if remove was
+            //  possible
+            Withdrawal => {
+                if client.remove_funds(trans.get_amount().unwrap()) {
+                    self.transactions.insert(t_id, trans);
+                    Ok(())
+                } else {
+                    Err(RejectReason::InsufficientFunds)
+                }
+            },
+            // hold funds from client and record transaction with the full
+            //  amount authorized if enough funds are available
+            Authorize => {
+                let amount = trans.get_amount().unwrap();
+                if (client.get_spendable_funds() >= amount) && client.hold_funds(amount) {
+                    let mut trans = trans;
+                    trans.set_authorized(amount);
+                    self.transactions.insert(t_id, trans);
+                    Ok(())
+                } else {
+                    Err(RejectReason::InsufficientFunds)
+                }
+            },
+            // if authorization was found settle all of its remaining amount, or
+            //  the amount given, from the held funds of client
+            Capture => {
+                match self.transactions.get_mut(&t_id) {
+                    Some(auth) if auth.get_type() == Authorize &&
+                                  auth.get_client_id() == client.get_client_id() => {
+                        let amount = trans.get_amount().unwrap_or(auth.get_authorized());
+                        if auth.get_authorized() <= 0.0 {
+                            Err(RejectReason::NotAuthorized)
+                        } else if amount > auth.get_authorized() {
+                            Err(RejectReason::ExceedsAuthorization)
+                        } else if client.remove_held_funds(amount) {
+                            auth.set_authorized(auth.get_authorized() - amount);
+                            Ok(())
+                        } else {
+                            Err(RejectReason::InsufficientFunds)
+                        }
+                    },
+                    _ => Err(RejectReason::UnknownTransaction)
+                }
+            },
+            // if authorization was found release its remaining amount back to
+            //  the available funds of client
+            Void => {
+                match self.transactions.get_mut(&t_id) {
+                    Some(auth) if auth.get_type() == Authorize &&
+                                  auth.get_client_id() == client.get_client_id() => {
+                        if auth.get_authorized() <= 0.0 {
+                            Err(RejectReason::NotAuthorized)
+                        } else if client.restore_funds(auth.get_authorized()) {
+                            auth.set_authorized(0.0);
+                            Ok(())
+                        } else {
+                            Err(RejectReason::InsufficientFunds)
+                        }
+                    },
+                    _ => Err(RejectReason::UnknownTransaction)
+                }
+            },
+            // apply admin operations to client and record transaction
+            Unlock | Freeze | Close | Adjust | Limit => {
+                match trans.get_type() {
+                    Unlock => client.unlock(),
+                    Freeze => client.freeze(),
+                    Close => client.close(),
+                    Limit => client.set_overdraft_limit(trans.get_amount().unwrap()),
+                    _ => client.adjust_funds(trans.get_amount().unwrap())
+                }
+                self.transactions.insert(t_id, trans);
+                Ok(())
+            },
+            // transfers always have a counterparty so are handled by
+            //  process_two_party
+            Transfer => Err(RejectReason::UnknownTransaction),
+            // if disputed transaction of client was found hold, release or
+            //  charge back funds of client
+            Dispute | Resolve | Chargeback => {
+                match self.transactions.get_mut(&t_id) {
+                    Some(trans_other) if matches!(trans_other.get_type(), Deposit | Withdrawal) &&
+                                         trans_other.get_client_id() == client.get_client_id() => {
+                        process_dispute(&trans, trans_other, client, None,
+                                        &self.config.policy)
+                    },
+                    _ => Err(RejectReason::UnknownTransaction)
+                }
+            }
+        }
+    }
+
+    /// Process a transaction that moves funds between the accounts of two
+    /// Clients
+    ///
+    /// A transfer is disputed as a unit: the funds are held in the receiving
+    /// account until the dispute is resolved, or charged back to the sending
+    /// account
+    fn process_two_party(&mut self, trans: Transaction, other_id: ClientID)
+            -> Result<(),RejectReason> {
+        // both clients were added by process_transaction and are never the
+        //  same client so this shouldn't panic
+        let [Some(client), Some(other)] =
+            self.clients.get_disjoint_mut([&trans.get_client_id(), &other_id])
+        else {
+            unreachable!("clients of a transfer must exist and be distinct");
+        };
+        let t_id = trans.get_id();
+
+        use crate::transaction::TransactionType::*;
+        match trans.get_type() {
+            // move funds from client to counterparty only if both sides of
+            //  the transfer can be applied
+            Transfer => {
+                if client.remove_funds(trans.get_amount().unwrap()) {
+                    other.add_funds(trans.get_amount().unwrap());
+                    self.transactions.insert(t_id, trans);
+                    Ok(())
+                } else {
+                    Err(RejectReason::InsufficientFunds)
+                }
+            },
+            Dispute | Resolve | Chargeback => {
+                // other_id is only set for disputes when the transfer was
+                //  found so unwrap shouldn't panic here
+                let transfer = self.transactions.get_mut(&t_id).unwrap();
+                let (source, dest) = if transfer.get_client_id() == other_id {
+                    (other, client)
+                } else {
+                    (client, other)
+                };
+
+                process_dispute(&trans, transfer, dest, Some(source),
+                                &self.config.policy)
+            },
+            _ => Err(RejectReason::UnknownTransaction)
+        }
+    }
+
+    /// Export Client info in CSV format
+    pub fn write_csv_to_stream<W>(&self, writer: W) -> Result<(),TransactionProcessorErr>
+            where W: io::Write {
+        use TransactionProcessorErr::*;
+
+        let mut csv_writer = csv::Writer::from_writer(writer);
+
+        for c in self.clients.values() {
+            csv_writer.serialize(c.to_record(self.legacy_output)).map_err(CSVError)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get a Client by its ID
+    #[allow(dead_code)]
+    pub fn get_client(&self, id: ClientID) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    /// Get the account state of every Client, in order of their ID
+    pub fn get_records(&self) -> Vec<ClientRecord> {
+        let mut records: Vec<ClientRecord> = self.clients.values()
+            .map(|c| c.to_record(self.legacy_output))
+            .collect();
+        records.sort_by_key(|r| r.client);
+        records
+    }
+
+    /// Export the event history of one or all Clients in the given format
+    ///
+    /// Clients are written in order of their ID
+    pub fn write_statement_to_stream<W>(&self, client: Option<ClientID>,
+                                        format: StatementFormat, writer: W)
+            -> Result<(),TransactionProcessorErr>
+            where W: io::Write {
+        use TransactionProcessorErr::*;
+
+        let clients = match client {
+            Some(id) => vec![self.clients.get(&id).ok_or(ClientNotFound(id))?],
+            None => {
+                let mut clients: Vec<&Client> = self.clients.values().collect();
+                clients.sort_by_key(|c| c.get_client_id());
+                clients
+            }
+        };
+
+        statement::write_statement(&clients, format, writer)
+    }
+
+    /// Print Client list to stdout
+    #[allow(dead_code)]
+    pub fn print_clients(&self) {
+        for c in self.clients.values() {
+            println!("{:?}",c);
+        }
+    }
+}
+
+/// Attribute an error to the input and line it came from, if read from an
+/// input
+fn source_error(input: Option<&Arc<str>>, line: u64, err: TransactionProcessorErr)
+        -> TransactionProcessorErr {
+    use TransactionProcessorErr::*;
+
+    match input {
+        Some(input) => SourceError(format!("{}:{}", input, line), Box::new(err)),
+        None => err
+    }
+}
+
+/// Get the key a file's next row is merged by, or None if it has no rows
+/// left
+///
+/// A row without a timestamp, or with an error, is keyed by the timestamp of
+/// the row before it in its file
+fn merge_key(head: &Option<Result<Transaction,Error>>, last: Option<Timestamp>)
+        -> Option<Option<Timestamp>> {
+    head.as_ref().map(|h| h.as_ref().ok().and_then(Transaction::get_timestamp).or(last))
+}
+
+/// Process a dispute, resolve or chargeback of a transaction
+///
+/// Disputed funds are held in the account of holder. Any part exceeding
+/// holder's available funds is handled as the policy says and recorded as
+/// receivable. A chargeback removes the held funds from holder, returns the
+/// disputed amount to source if given and locks holder if the policy says so
+///
+/// Note: Returning funds to source that weren't held needs holder to have
+/// them available, so the money moved is never more than holder loses
+fn process_dispute(trans: &Transaction, disputed: &mut Transaction,
+                   holder: &mut Client, source: Option<&mut Client>,
+                   policy: &Policy) -> Result<(),RejectReason> {
+    use crate::transaction::TransactionType::*;
+    match trans.get_type() {
+        // hold the amount given, or all of the undisputed amount, of the
+        //  disputed transaction
+        Dispute => {
+            let undisputed = disputed.get_undisputed_amount();
+            let amount = trans.get_amount().unwrap_or(undisputed);
+            let window_closed = match (policy.get_dispute_window(),
+                                       trans.get_timestamp(), disputed.get_timestamp()) {
+                (Some(window), Some(at), Some(made)) => at.saturating_sub(made) > window,
+                _ => false
+            };
+            if window_closed {
+                Err(RejectReason::DisputeWindowClosed)
+            } else if undisputed <= 0.0 {
+                Err(RejectReason::AlreadyDisputed)
+            } else if amount > undisputed {
+                Err(RejectReason::ExceedsUndisputed)
+            } else {
+                let shortfall =
+                    (amount - holder.get_available_funds().max(0.0)).max(0.0);
+                let hold = match policy.negative_balance {
+                    NegativeBalanceMode::Allow => amount,
+                    NegativeBalanceMode::Cap => amount - shortfall,
+                    NegativeBalanceMode::Reject if shortfall > 0.0 => {
+                        return Err(RejectReason::InsufficientFunds);
+                    },
+                    NegativeBalanceMode::Reject => amount
+                };
+                holder.hold_funds(hold);
+                holder.add_receivable(shortfall);
+                holder.add_dispute();
+                if !disputed.is_disputed() {
+                    disputed.set_disputed_at(trans.get_timestamp());
+                }
+                disputed.add_disputed(amount);
+                disputed.add_shortfall(shortfall, hold == amount);
+                Ok(())
+            }
+        },
+        // release all of the held funds and clear the shortfall of the
+        //  disputed transaction
+        Resolve => {
+            let amount = disputed.get_disputed_amount();
+            let (held, shortfall) = disputed.split_disputed(amount);
+            if !disputed.is_disputed() {
+                Err(RejectReason::NotDisputed)
+            } else if holder.restore_funds(held) {
+                holder.remove_receivable(shortfall);
+                disputed.remove_disputed(amount);
+                Ok(())
+            } else {
+                Err(RejectReason::InsufficientFunds)
+            }
+        },
+        // remove the amount given, or all, of the disputed amount of the
+        //  disputed transaction. Any shortfall stays receivable, unless the
+        //  source of a transfer is paid back, when any of it that wasn't held
+        //  must come out of the available funds of holder
+        Chargeback => {
+            let amount = trans.get_amount().unwrap_or(disputed.get_disputed_amount());
+            let (held, _) = disputed.split_disputed(amount);
+            let unheld = if source.is_some() { disputed.get_unheld_amount(amount) } else { 0.0 };
+            if !disputed.is_disputed() {
+                Err(RejectReason::NotDisputed)
+            } else if amount > disputed.get_disputed_amount() {
+                Err(RejectReason::ExceedsDisputed)
+            } else if ((unheld <= 0.0) || (holder.get_available_funds() >= unheld)) &&
+                      holder.remove_held_funds(held) {
+                holder.add_chargeback();
+                if policy.should_lock(holder.get_chargebacks()) {
+                    holder.lock();
+                }
+                if let Some(source) = source {
+                    holder.remove_funds(unheld);
+                    holder.remove_receivable(unheld);
+                    source.add_funds(amount);
+                }
+                disputed.add_charged_back(amount);
+                Ok(())
+            } else {
+                Err(RejectReason::InsufficientFunds)
+            }
+        },
+        _ => Err(RejectReason::UnknownTransaction)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::*;
+    use crate::status::AccountStatus;
+    use crate::transaction::*;
+
+    #[test]
+    fn in_out_basic() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             deposit, 1, 3, 2.0\n\
+             withdrawal, 1, 4, 1.5";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,1.5000,0.0000,1.5000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+    }
+
+    #[test]
+    fn in_out_resolve() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 100.0\n\
+             dispute, 1, 1,\n\
+             resolve, 1, 1,";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,100.0000,0.0000,100.0000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+    }
+
+    #[test]
+    fn in_out_chargeback() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 100.0\n\
+             dispute, 1, 1,\n\
+             chargeback, 1, 1,";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,0.0000,0.0000,0.0000,true,locked,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+    }
+
+    #[test]
+    fn bad_input1() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             deposit, 1, 3,\n\
+             withdrawal, 1, 4, 1.5";
+
+        let mut tp = TransactionProcessor::new();
+        let result = tp.process_csv_stream(input.as_bytes()).unwrap_err();
+        match result {
+            TransactionProcessorErr::TransactionValidateError(_) => (),
+            _ => panic!("incorrect result")
+        }
+    }
+
+    #[test]
+    fn bad_input2() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             deposit,\n\
+             withdrawal, 1, 4, 1.5";
+
+        let mut tp = TransactionProcessor::new();
+        let result = tp.process_csv_stream(input.as_bytes()).unwrap_err();
+        match result {
+            TransactionProcessorErr::CSVError(_) => (),
+            _ => panic!("incorrect result")
+        }
+    }
+
+    #[test]
+    fn deposit() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let t = Transaction::new_deposit(c_id, t_id, amount, false);
+
+        tp.process_transaction(t);
+
+        let c = tp.clients.get(&c_id);
+        assert!(c.is_some());   // ensure client was created
+        assert_eq!(c.unwrap().get_available_funds(),amount);
+        assert!(tp.transactions.contains_key(&t_id));
+    }
+
+    #[test]
+    fn withdrawal() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+        let wothdraw_amount = 10.0;
+
+        let mut tp = TransactionProcessor::new();
+        let mut c = Client::new(c_id);
+        c.add_funds(amount);
+        tp.clients.insert(c_id, c);
+
+        let t = Transaction::new_withdrawl(c_id, t_id, wothdraw_amount, false);
+
+        tp.process_transaction(t);
+
+        let ec = tp.clients.get(&c_id).unwrap();
+        assert_eq!(ec.get_available_funds(),amount-wothdraw_amount);
+    }
+
+    #[test]
+    fn dispute() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let c = Client::new(c_id);
+        tp.clients.insert(c_id, c);
+
+        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
+        let t2 = Transaction::new_dispute(c_id, t_id);
+
+        tp.process_transaction(t1);
+        tp.process_transaction(t2);
+
+        let ec = tp.clients.get(&c_id).unwrap();
+        assert_eq!(ec.get_held_funds(),amount);
+
+        let et = tp.transactions.get(&t_id).unwrap();
+        assert!(et.is_disputed());
+    }
+
+    #[test]
+    fn resolve() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let c = Client::new(c_id);
+        tp.clients.insert(c_id, c);
+
+        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
+        let t2 = Transaction::new_dispute(c_id, t_id);
+        let t3 = Transaction::new_resolve(c_id, t_id);
+
+        tp.process_transaction(t1);
+        tp.process_transaction(t2);
+        tp.process_transaction(t3);
+
+        let ec = tp.clients.get(&c_id).unwrap();
+        assert_eq!(ec.get_available_funds(),amount);
+
+        let et = tp.transactions.get(&t_id).unwrap();
+        assert!(!et.is_disputed());
+    }
+
+    #[test]
+    fn chargeback() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let c = Client::new(c_id);
+        tp.clients.insert(c_id, c);
+
+        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
+        let t2 = Transaction::new_dispute(c_id, t_id);
+        let t3 = Transaction::new_chargeback(c_id, t_id);
+
+        tp.process_transaction(t1);
+        tp.process_transaction(t2);
+        tp.process_transaction(t3);
+
+        let ec = tp.clients.get(&c_id).unwrap();
+        assert_eq!(ec.get_available_funds(),0.0);
+        assert!(ec.is_locked());
+
+        let et = tp.transactions.get(&t_id).unwrap();
+        assert!(!et.is_disputed());
+    }
+
+    #[test]
+    fn history() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 100.0\n\
+             withdrawal, 1, 2, 150.0\n\
+             dispute, 1, 1,\n\
+             chargeback, 1, 1,\n\
+             deposit, 1, 3, 10.0";
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        use crate::history::Outcome::*;
+        let history = tp.clients.get(&1).unwrap().get_history();
+        let outcomes: Vec<_> = history.iter()
+            .map(|e| (e.get_id(), e.get_outcome(), e.get_reason()))
+            .collect();
+        assert_eq!(outcomes, vec![
+            (1, Applied, None),
+            (2, Rejected, Some(RejectReason::InsufficientFunds)),
+            (1, Applied, None),
+            (1, Applied, None),
+            (3, Rejected, Some(RejectReason::AccountLocked))
+        ]);
+        assert_eq!(history[3].get_changes().held, -100.0);
+        assert_eq!(history[3].get_changes().available, 0.0);
+    }
+
+    #[test]
+    fn history_existing_clients() {
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(1, 1, 10.0, false));
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(1, 2, 5.0, false));
+        tp.process_transaction(Transaction::new_deposit(2, 3, 5.0, false));
+
+        let history = tp.clients.get(&1).unwrap().get_history();
+        assert_eq!(history.iter().map(|e| e.get_id()).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(history[0].get_changes().available, 5.0);
+        assert_eq!(tp.clients.get(&2).unwrap().get_history().len(), 1);
+
+        tp.set_record_history(false);
+        tp.process_transaction(Transaction::new_deposit(1, 4, 5.0, false));
+        assert!(tp.clients.get(&1).unwrap().get_history().is_empty());
+    }
+
+    #[test]
+    fn statement_csv() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             deposit, 2, 2, 2.0\n\
+             withdrawal, 1, 3, 1.5";
+        let expected_out =
+            "client,tx,type,timestamp,synthetic,outcome,reason,available_change,held_change,total_change,available,held,total,receivable,status,flags,source\n\
+             1,1,deposit,,false,applied,,1.0000,0.0000,1.0000,1.0000,0.0000,1.0000,0.0000,active,,\n\
+             1,3,withdrawal,,false,rejected,insufficient_funds,0.0000,0.0000,0.0000,1.0000,0.0000,1.0000,0.0000,active,,\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_statement_to_stream(Some(1), StatementFormat::Csv, &mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+    }
+
+    #[test]
+    fn statement_opening_balances() {
+        let opening =
+            "client,available,held,total,locked\n\
+             1,10.0000,0.0000,10.0000,false\n";
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.load_opening_balances(opening.as_bytes()).unwrap();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_statement_to_stream(Some(1), StatementFormat::Csv, &mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap();
+
+        assert!(out.ends_with("1,1,deposit,,false,applied,,1.0000,0.0000,1.0000,11.0000,0.0000,\
+                               11.0000,0.0000,active,,\n"), "{}", out);
+    }
+
+    #[test]
+    fn statement_unknown_client() {
+        let tp = TransactionProcessor::new();
+        let result = tp.write_statement_to_stream(Some(1), StatementFormat::Text,
+                                                  io::sink()).unwrap_err();
+        match result {
+            TransactionProcessorErr::ClientNotFound(1) => (),
+            _ => panic!("incorrect result")
+        }
+    }
+
+    #[test]
+    fn in_out_transfer() {
+        let input =
+            "type, client, tx, amount, counterparty\n\
+             deposit, 1, 1, 10.0,\n\
+             transfer, 1, 2, 4.0, 2\n\
+             transfer, 1, 3, 7.0, 2";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,6.0000,0.0000,6.0000,false,active,0.0000,0.0000\n\
+             2,4.0000,0.0000,4.0000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        // client order isn't defined so compare sorted lines
+        let mut lines: Vec<_> = out.lines().collect();
+        lines.sort();
+        let mut expected_lines: Vec<_> = expected_out.lines().collect();
+        expected_lines.sort();
+        assert_eq!(lines, expected_lines);
+    }
+
+    #[test]
+    fn transfer() {
+        let (src_id, dst_id) = (500, 501);
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let mut c = Client::new(src_id);
+        c.add_funds(amount);
+        tp.clients.insert(src_id, c);
+
+        let t = Transaction::new_transfer(src_id, dst_id, t_id, 40.0, false);
+        tp.process_transaction(t);
+
+        assert_eq!(tp.clients.get(&src_id).unwrap().get_available_funds(), 60.0);
+        assert_eq!(tp.clients.get(&dst_id).unwrap().get_available_funds(), 40.0);
+        assert!(tp.transactions.contains_key(&t_id));
+    }
+
+    #[test]
+    fn transfer_locked_counterparty() {
+        let (src_id, dst_id) = (500, 501);
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        let mut c = Client::new(src_id);
+        c.add_funds(amount);
+        tp.clients.insert(src_id, c);
+        let mut c = Client::new(dst_id);
+        c.lock();
+        tp.clients.insert(dst_id, c);
+
+        let t = Transaction::new_transfer(src_id, dst_id, 600, 40.0, false);
+        tp.process_transaction(t);
+
+        // neither side of the transfer should be applied
+        assert_eq!(tp.clients.get(&src_id).unwrap().get_available_funds(), amount);
+        assert_eq!(tp.clients.get(&dst_id).unwrap().get_available_funds(), 0.0);
+        assert!(tp.transactions.is_empty());
+    }
+
+    #[test]
+    fn transfer_insufficient_funds() {
+        let (src_id, dst_id) = (500, 501);
+
+        let mut tp = TransactionProcessor::new();
+        let t = Transaction::new_transfer(src_id, dst_id, 600, 40.0, false);
+        tp.process_transaction(t);
+
+        assert_eq!(tp.clients.get(&src_id).unwrap().get_available_funds(), 0.0);
+        assert_eq!(tp.clients.get(&dst_id).unwrap().get_available_funds(), 0.0);
+        assert!(tp.transactions.is_empty());
+    }
+
+    #[test]
+    fn transfer_dispute_resolve() {
+        let (src_id, dst_id) = (500, 501);
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(src_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_transfer(src_id, dst_id, t_id, 40.0, false));
+        tp.process_transaction(Transaction::new_dispute(src_id, t_id));
+
+        let dst = tp.clients.get(&dst_id).unwrap();
+        assert_eq!(dst.get_available_funds(), 0.0);
+        assert_eq!(dst.get_held_funds(), 40.0);
+        assert!(tp.transactions.get(&t_id).unwrap().is_disputed());
+
+        tp.process_transaction(Transaction::new_resolve(dst_id, t_id));
+
+        let dst = tp.clients.get(&dst_id).unwrap();
+        assert_eq!(dst.get_available_funds(), 40.0);
+        assert_eq!(dst.get_held_funds(), 0.0);
+        assert!(!tp.transactions.get(&t_id).unwrap().is_disputed());
+    }
+
+    #[test]
+    fn transfer_chargeback() {
+        let (src_id, dst_id) = (500, 501);
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(src_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_transfer(src_id, dst_id, t_id, 40.0, false));
+        tp.process_transaction(Transaction::new_dispute(src_id, t_id));
+        tp.process_transaction(Transaction::new_chargeback(src_id, t_id));
+
+        let src = tp.clients.get(&src_id).unwrap();
+        assert_eq!(src.get_available_funds(), 100.0);
+        assert!(!src.is_locked());
+
+        let dst = tp.clients.get(&dst_id).unwrap();
+        assert_eq!(dst.get_total_funds(), 0.0);
+        assert!(dst.is_locked());
+        assert!(!tp.transactions.get(&t_id).unwrap().is_disputed());
+    }
+
+    #[test]
+    fn transfer_chargeback_capped() {
+        let (src_id, dst_id) = (500, 501);
+        let t_id = 600;
+
+        let mut config = Config::default();
+        config.policy.negative_balance = NegativeBalanceMode::Cap;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_checked(true);
+        tp.process_transaction(Transaction::new_deposit(src_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_transfer(src_id, dst_id, t_id, 40.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(dst_id, 2, 30.0, false));
+        tp.process_transaction(Transaction::new_dispute(src_id, t_id));
+        let sum = |tp: &TransactionProcessor| {
+            tp.clients.values().map(|c| c.get_total_funds()).sum::<f64>()
+        };
+
+        // the 30 that wasn't held can't be paid back yet
+        tp.process_transaction(Transaction::new_chargeback(src_id, t_id));
+        assert!(tp.transactions.get(&t_id).unwrap().is_disputed());
+        assert_eq!(tp.clients.get(&src_id).unwrap().get_total_funds(), 60.0);
+        assert_eq!(sum(&tp), 70.0);
+
+        tp.process_transaction(Transaction::new_deposit(dst_id, 3, 30.0, false));
+        tp.process_transaction(Transaction::new_chargeback(src_id, t_id));
+        assert!(!tp.transactions.get(&t_id).unwrap().is_disputed());
+        assert_eq!(tp.clients.get(&src_id).unwrap().get_total_funds(), 100.0);
+        let dst = tp.clients.get(&dst_id).unwrap();
+        assert_eq!(dst.get_total_funds(), 0.0);
+        assert_eq!(dst.get_receivable(), 0.0);
+        assert_eq!(sum(&tp), 100.0);
+    }
+
+    #[test]
+    fn transfer_dispute_non_party() {
+        let (src_id, dst_id) = (500, 501);
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(src_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_transfer(src_id, dst_id, t_id, 40.0, false));
+        tp.process_transaction(Transaction::new_dispute(502, t_id));
+
+        assert_eq!(tp.clients.get(&dst_id).unwrap().get_held_funds(), 0.0);
+        assert_eq!(tp.clients.get(&502).unwrap().get_held_funds(), 0.0);
+        assert!(!tp.transactions.get(&t_id).unwrap().is_disputed());
+    }
+
+    #[test]
+    fn authorize_capture() {
+        let c_id = 500;
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, t_id, 40.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 60.0);
+        assert_eq!(c.get_held_funds(), 40.0);
+
+        tp.process_transaction(Transaction::new_capture(c_id, t_id, Some(15.0)));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 60.0);
+        assert_eq!(c.get_held_funds(), 25.0);
+        assert_eq!(c.get_total_funds(), 85.0);
+        assert_eq!(tp.transactions.get(&t_id).unwrap().get_authorized(), 25.0);
+
+        // capturing more than the remaining authorization is rejected
+        tp.process_transaction(Transaction::new_capture(c_id, t_id, Some(30.0)));
+        assert_eq!(tp.clients.get(&c_id).unwrap().get_held_funds(), 25.0);
+
+        tp.process_transaction(Transaction::new_capture(c_id, t_id, None));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 60.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_total_funds(), 60.0);
+        assert_eq!(tp.transactions.get(&t_id).unwrap().get_authorized(), 0.0);
+    }
+
+    #[test]
+    fn authorize_capture_disputed() {
+        let c_id = 500;
+
+        // the authorized funds leave the account even with the deposit
+        //  behind them disputed
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, 2, 100.0));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_capture(c_id, 2, None));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_total_funds(), 0.0);
+        assert_eq!(tp.transactions.get(&2).unwrap().get_authorized(), 0.0);
+    }
+
+    #[test]
+    fn authorize_void() {
+        let c_id = 500;
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, t_id, 40.0));
+        tp.process_transaction(Transaction::new_capture(c_id, t_id, Some(10.0)));
+        tp.process_transaction(Transaction::new_void(c_id, t_id));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 90.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_total_funds(), 90.0);
+
+        // a voided authorization can't be captured
+        tp.process_transaction(Transaction::new_capture(c_id, t_id, None));
+        assert_eq!(tp.clients.get(&c_id).unwrap().get_total_funds(), 90.0);
+    }
+
+    #[test]
+    fn authorize_insufficient_funds() {
+        let c_id = 500;
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 10.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, t_id, 40.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 10.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert!(!tp.transactions.contains_key(&t_id));
+        assert_eq!(c.get_history()[1].get_reason(),
+                   Some(RejectReason::InsufficientFunds));
+    }
+
+    #[test]
+    fn dispute_authorization() {
+        let c_id = 500;
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, t_id, 40.0));
+        tp.process_transaction(Transaction::new_dispute(c_id, t_id));
+
+        assert_eq!(tp.clients.get(&c_id).unwrap().get_held_funds(), 40.0);
+        assert!(!tp.transactions.get(&t_id).unwrap().is_disputed());
+    }
+
+    #[test]
+    fn partial_dispute_chargeback() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 100.0\n\
+             dispute, 1, 1, 30.0\n\
+             dispute, 1, 1, 80.0\n\
+             dispute, 1, 1, 20.0\n\
+             chargeback, 1, 1, 10.0";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,50.0000,40.0000,90.0000,true,locked,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+
+        let t = tp.transactions.get(&1).unwrap();
+        assert_eq!(t.get_disputed_amount(), 40.0);
+        assert_eq!(t.get_charged_back_amount(), 10.0);
+        assert_eq!(t.get_undisputed_amount(), 50.0);
+    }
+
+    #[test]
+    fn dispute_twice() {
+        let c_id = 500;
+        let t_id = 600;
+        let amount = 100.0;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, t_id, amount, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, t_id));
+        tp.process_transaction(Transaction::new_dispute(c_id, t_id));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_held_funds(), amount);
+        assert_eq!(c.get_history()[2].get_reason(),
+                   Some(RejectReason::AlreadyDisputed));
+    }
+
+    #[test]
+    fn chargeback_exceeds_disputed() {
+        let c_id = 500;
+        let t_id = 600;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, t_id, 100.0, false));
+        tp.process_transaction(Transaction::new_partial_dispute(c_id, t_id, 20.0));
+        tp.process_transaction(Transaction::new_partial_chargeback(c_id, t_id, 30.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_held_funds(), 20.0);
+        assert!(!c.is_locked());
+    }
+
+    #[test]
+    fn admin_not_allowed() {
+        let input =
+            "type, client, tx, amount, reason\n\
+             deposit, 1, 1, 100.0,\n\
+             adjust, 1, 2, -40.0, duplicate deposit";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,100.0000,0.0000,100.0000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+        assert!(!tp.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn in_out_admin() {
+        let input =
+            "type, client, tx, amount, reason\n\
+             deposit, 1, 1, 100.0,\n\
+             dispute, 1, 1,,\n\
+             chargeback, 1, 1,,\n\
+             unlock, 1, 2,, chargeback reversed by bank\n\
+             adjust, 1, 3, 100.0, chargeback reversed by bank";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,100.0000,0.0000,100.0000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.set_allow_admin(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+        assert_eq!(tp.transactions.get(&3).unwrap().get_reason(),
+                   Some("chargeback reversed by bank"));
+    }
+
+    #[test]
+    fn freeze() {
+        use crate::transaction::TransactionType::*;
+        let c_id = 500;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_allow_admin(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_admin(Freeze, c_id, 2, None, None));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 4, 10.0, false));
+        tp.process_transaction(Transaction::new_transfer(c_id, 501, 5, 10.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, 6, 10.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_status(), AccountStatus::Frozen);
+        assert_eq!(c.get_available_funds(), 110.0);
+        assert!(!tp.transactions.contains_key(&3));
+        assert!(!tp.transactions.contains_key(&5));
+        assert!(!tp.transactions.contains_key(&6));
+    }
+
+    #[test]
+    fn close() {
+        use crate::transaction::TransactionType::*;
+        let (c_id, o_id) = (500, 501);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_allow_admin(true);
+        tp.process_transaction(Transaction::new_deposit(o_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_admin(Close, c_id, 2, None, None));
+        tp.process_transaction(Transaction::new_deposit(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_transfer(o_id, c_id, 4, 10.0, false));
+
+        assert_eq!(tp.clients.get(&c_id).unwrap().get_status(), AccountStatus::Closed);
+        assert_eq!(tp.clients.get(&c_id).unwrap().get_total_funds(), 0.0);
+        assert_eq!(tp.clients.get(&o_id).unwrap().get_total_funds(), 100.0);
+        assert!(tp.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn in_out_legacy() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             deposit, 1, 3, 2.0\n\
+             withdrawal, 1, 4, 1.5";
+        let expected_out =
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.set_legacy_output(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+
+        assert_eq!(out, expected_out);
+    }
+
+    #[test]
+    fn status_matrix() {
+        use crate::transaction::TransactionType::*;
+        let c_id = 500;
+        let t_id = 600;
+
+        // allow open disputes on locked accounts to be resolved
+        let mut config = Config::default();
+        config.status.set_allowed(AccountStatus::Locked, Resolve, true);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, t_id, 50.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_dispute(c_id, t_id));
+        tp.process_transaction(Transaction::new_chargeback(c_id, 1));
+        tp.process_transaction(Transaction::new_resolve(c_id, t_id));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_status(), AccountStatus::Locked);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_available_funds(), 50.0);
+    }
+
+    #[test]
+    fn status_rejects() {
+        let c_id = 500;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.clients.get_mut(&c_id).unwrap().set_status(AccountStatus::Dormant);
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 10.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_transfer(c_id, 501, 4, 10.0, false));
+        // a dormant account can receive a transfer like a deposit
+        tp.process_transaction(Transaction::new_deposit(501, 5, 10.0, false));
+        tp.process_transaction(Transaction::new_transfer(501, c_id, 6, 5.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 115.0);
+        assert_eq!(c.get_history()[1].get_reason(), Some(RejectReason::AccountDormant));
+        assert_eq!(c.get_history()[3].get_reason(), Some(RejectReason::AccountDormant));
+        assert_eq!(c.get_history()[4].get_reason(), None);
+        assert_eq!(tp.clients.get(&501).unwrap().get_available_funds(), 5.0);
+    }
+
+    #[test]
+    fn policy_lock_after_chargebacks() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.policy.lock_after_chargebacks = 2;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        for t_id in 1..=3 {
+            tp.process_transaction(Transaction::new_deposit(c_id, t_id, 10.0, false));
+            tp.process_transaction(Transaction::new_dispute(c_id, t_id));
+            tp.process_transaction(Transaction::new_chargeback(c_id, t_id));
+
+            let c = tp.clients.get(&c_id).unwrap();
+            assert_eq!(c.is_locked(), t_id >= 2);
+        }
+
+        // the third deposit was rejected since the account was locked
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_chargebacks(), 2);
+        assert_eq!(c.get_total_funds(), 0.0);
+    }
+
+    #[test]
+    fn policy_locked_accounts() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.policy.locked_accepts_deposits = true;
+        config.policy.locked_disputes_move_funds = true;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 2, 50.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_dispute(c_id, 2));
+        tp.process_transaction(Transaction::new_chargeback(c_id, 1));
+        tp.process_transaction(Transaction::new_resolve(c_id, 2));
+        tp.process_transaction(Transaction::new_deposit(c_id, 3, 5.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 4, 5.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert!(c.is_locked());
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_available_funds(), 55.0);
+    }
+
+    #[test]
+    fn negative_balance_allow() {
+        let c_id = 500;
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 70.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), -70.0);
+        assert_eq!(c.get_held_funds(), 100.0);
+        assert_eq!(c.get_receivable(), 70.0);
+
+        tp.process_transaction(Transaction::new_chargeback(c_id, 1));
+
+        // the shortfall stays receivable after a chargeback
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), -70.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_receivable(), 70.0);
+    }
+
+    #[test]
+    fn negative_balance_cap() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.policy.negative_balance = NegativeBalanceMode::Cap;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 70.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 0.0);
+        assert_eq!(c.get_held_funds(), 30.0);
+        assert_eq!(c.get_receivable(), 70.0);
+
+        tp.process_transaction(Transaction::new_resolve(c_id, 1));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 30.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_receivable(), 0.0);
+
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_partial_chargeback(c_id, 1, 50.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_available_funds(), 0.0);
+        assert_eq!(c.get_held_funds(), 15.0);
+        assert_eq!(c.get_receivable(), 70.0);
+    }
+
+    #[test]
+    fn negative_balance_reject() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.policy.negative_balance = NegativeBalanceMode::Reject;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 70.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_partial_dispute(c_id, 1, 30.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_history()[2].get_reason(), Some(RejectReason::InsufficientFunds));
+        assert_eq!(c.get_history()[3].get_reason(), None);
+        assert_eq!(c.get_available_funds(), 0.0);
+        assert_eq!(c.get_held_funds(), 30.0);
+        assert_eq!(c.get_receivable(), 0.0);
+    }
+
+    #[test]
+    fn client_config_overdraft() {
+        let config =
+            "client, overdraft_limit\n\
+             1, 50.0\n\
+             2,";
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 20.0\n\
+             withdrawal, 1, 2, 60.0\n\
+             withdrawal, 1, 3, 20.0\n\
+             deposit, 2, 4, 20.0\n\
+             withdrawal, 2, 5, 30.0";
+        let expected_out =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,-40.0000,0.0000,-40.0000,false,active,0.0000,40.0000\n\
+             2,20.0000,0.0000,20.0000,false,active,0.0000,0.0000\n";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_client_config_stream(config.as_bytes()).unwrap();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_csv_to_stream(&mut out_buf).unwrap();
+        let mut out: Vec<String> = std::str::from_utf8(out_buf.as_slice())
+            .unwrap().lines().map(String::from).collect();
+        out.sort();
+        let mut expected: Vec<String> = expected_out.lines().map(String::from).collect();
+        expected.sort();
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn statement_client_config() {
+        let config =
+            "client, overdraft_limit\n\
+             1, 50.0";
+        let input =
+            "type, client, tx, amount\n\
+             withdrawal, 1, 1, 20.0";
+
+        let mut out_buf = Vec::new();
+        let mut tp = TransactionProcessor::new();
+        tp.process_client_config_stream(config.as_bytes()).unwrap();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        tp.write_statement_to_stream(Some(1), StatementFormat::Csv, &mut out_buf).unwrap();
+        let out = std::str::from_utf8(out_buf.as_slice()).unwrap();
+
+        assert!(out.ends_with("1,1,withdrawal,,false,applied,,-20.0000,0.0000,-20.0000,\
+                               -20.0000,0.0000,-20.0000,0.0000,active,,\n"), "{}", out);
+    }
+
+    #[test]
+    fn client_config_invalid() {
+        let config =
+            "client, overdraft_limit\n\
+             1, -50.0";
+
+        let mut tp = TransactionProcessor::new();
+        let res = tp.process_client_config_stream(config.as_bytes());
+
+        assert!(matches!(res, Err(TransactionProcessorErr::ClientConfigValidateError(_))));
+    }
+
+    #[test]
+    fn admin_limit() {
+        let c_id = 500;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_allow_admin(true);
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 1, 10.0, false));
+        tp.process_transaction(Transaction::new_admin(TransactionType::Limit, c_id, 2,
+                                                      Some(25.0), Some("credit line")));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, 4, 15.0));
+        tp.process_transaction(Transaction::new_authorize(c_id, 5, 1.0));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_overdraft_limit(), 25.0);
+        assert_eq!(c.get_available_funds(), -25.0);
+        assert_eq!(c.get_held_funds(), 15.0);
+        assert_eq!(c.get_credit_used(), 25.0);
+    }
+
+    #[test]
+    fn velocity_limits() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.velocity.window = 3;
+        config.velocity.max_count = Some(2);
+        config.velocity.max_total = Some(50.0);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 10.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 4, 10.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 5, 10.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 6, 45.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 7, 40.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        let reasons: Vec<Option<RejectReason>> =
+            c.get_history().iter().map(|e| e.get_reason()).collect();
+        assert_eq!(reasons, vec![
+            None, None, None, Some(RejectReason::VelocityCount), None,
+            Some(RejectReason::VelocityTotal), None
+        ]);
+        assert_eq!(c.get_available_funds(), 50.0);
+        assert_eq!(c.get_status(), AccountStatus::Active);
+    }
+
+    #[test]
+    fn velocity_outbound() {
+        let c_id = 500;
+
+        let mut config = Config::default();
+        config.velocity.window = 10;
+        config.velocity.max_total = Some(50.0);
+        config.velocity.freeze = true;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_authorize(c_id, 2, 30.0));
+        tp.process_transaction(Transaction::new_capture(c_id, 2, None));
+        tp.process_transaction(Transaction::new_transfer(c_id, 501, 3, 30.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert_eq!(c.get_status(), AccountStatus::Frozen);
+        assert_eq!(c.get_total_funds(), 70.0);
+        assert!(!tp.transactions.contains_key(&3));
+    }
+
+    #[test]
+    fn velocity_tier_freeze() {
+        let config = Config::from_toml(
+            "[tiers.watched.velocity]\n\
+             window = 10\n\
+             max_total = 50.0\n\
+             freeze = true\n").unwrap();
+        let client_config =
+            "client, overdraft_limit, tier\n\
+             1,, watched";
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_client_config_stream(client_config.as_bytes()).unwrap();
+        for (c_id, t_id) in [(1, 1), (2, 2)] {
+            tp.process_transaction(Transaction::new_deposit(c_id, t_id, 100.0, false));
+        }
+        tp.process_transaction(Transaction::new_withdrawl(1, 3, 60.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(2, 4, 60.0, false));
+
+        let c1 = tp.clients.get(&1).unwrap();
+        assert_eq!(c1.get_status(), AccountStatus::Frozen);
+        assert_eq!(c1.get_available_funds(), 100.0);
+        let c2 = tp.clients.get(&2).unwrap();
+        assert_eq!(c2.get_status(), AccountStatus::Active);
+        assert_eq!(c2.get_available_funds(), 40.0);
+    }
+
+    #[test]
+    fn rules_flag_reject() {
+        let c_id = 500;
+        let config = Config::from_toml(
+            "[[rules]]\n\
+             rule = \"large_amount\"\n\
+             threshold = 1000.0\n\
+             \n\
+             [[rules]]\n\
+             rule = \"structuring\"\n\
+             threshold = 1000.0\n\
+             margin = 50.0\n\
+             count = 2\n\
+             window = 5\n\
+             action = \"reject\"\n").unwrap();
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 5000.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 2, 990.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 3, 980.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        let history = c.get_history();
+        assert_eq!(history[0].get_flags(), "large_amount");
+        assert_eq!(history[0].get_reason(), None);
+        assert_eq!(history[1].get_flags(), "");
+        assert_eq!(history[2].get_reason(), Some(RejectReason::Structuring));
+        assert_eq!(history[2].get_flags(), "structuring");
+        assert_eq!(c.get_available_funds(), 5990.0);
+    }
+
+    #[test]
+    fn rules_lock() {
+        use crate::rules::*;
+        let c_id = 500;
+
+        let mut tp = TransactionProcessor::new();
+        tp.add_rule(Box::new(ManyDisputes { max: 1, action: Action::Lock }));
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 10.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 2, 10.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 1));
+        tp.process_transaction(Transaction::new_dispute(c_id, 2));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        assert!(c.is_locked());
+        assert_eq!(c.get_disputes(), 1);
+        assert_eq!(c.get_held_funds(), 10.0);
+    }
+
+    #[test]
+    fn rules_dsl() {
+        let c_id = 500;
+        let rules = crate::dsl::parse_rules(
+            "when tx == 5 then allow\n\
+             when type == withdrawal and amount > 100 and client.disputes >= 1\n\
+             \x20   then reject \"high risk\"\n").unwrap();
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        for rule in rules {
+            tp.add_rule(Box::new(rule));
+        }
+        tp.process_transaction(Transaction::new_deposit(c_id, 1, 500.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 2, 200.0, false));
+        tp.process_transaction(Transaction::new_deposit(c_id, 3, 10.0, false));
+        tp.process_transaction(Transaction::new_dispute(c_id, 3));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 4, 200.0, false));
+        tp.process_transaction(Transaction::new_withdrawl(c_id, 5, 200.0, false));
+
+        let c = tp.clients.get(&c_id).unwrap();
+        let history = c.get_history();
+        assert_eq!(history[1].get_reason(), None);
+        assert_eq!(history[4].get_reason(), Some(RejectReason::RuleRejected));
+        assert_eq!(history[4].get_flags(), "high risk");
+        // an allow rule stops the later reject rule being checked
+        assert_eq!(history[5].get_reason(), None);
+        assert_eq!(history[5].get_flags(), "");
+        assert_eq!(c.get_available_funds(), 100.0);
+    }
+
+    #[test]
+    fn rules_transfer_counterparty() {
+        let rules = crate::dsl::parse_rules(
+            "when client.tier == \"watched\" then lock \"watched\"\n").unwrap();
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        for rule in rules {
+            tp.add_rule(Box::new(rule));
+        }
+        tp.process_transaction(Transaction::new_deposit(1, 1, 100.0, false));
+        tp.process_transaction(Transaction::new_deposit(2, 2, 10.0, false));
+        tp.clients.get_mut(&2).unwrap().set_tier(Some("watched".to_string()));
+        tp.process_transaction(Transaction::new_transfer(1, 2, 3, 40.0, false));
+
+        // the rule matched the receiving account, not the sending one
+        let c1 = tp.clients.get(&1).unwrap();
+        assert_eq!(c1.get_history()[1].get_reason(), Some(RejectReason::RuleRejected));
+        assert_eq!(c1.get_history()[1].get_flags(), "");
+        assert_eq!(c1.get_available_funds(), 100.0);
+        assert!(!c1.is_locked());
+        let c2 = tp.clients.get(&2).unwrap();
+        assert_eq!(c2.get_history()[1].get_flags(), "watched");
+        assert!(c2.is_locked());
+    }
+
+    #[test]
+    fn timestamps_out_of_order() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, 2024-01-01T00:00:10Z\n\
+             deposit, 1, 2, 10.0, 1704067205\n\
+             deposit, 1, 3, 10.0, 2024-01-01T00:00:10Z\n\
+             deposit, 2, 4, 10.0, 1704067205\n\
+             deposit, 2, 5, 10.0,";
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        let c = tp.clients.get(&1).unwrap();
+        let history = c.get_history();
+        assert_eq!(history[0].get_timestamp(), Some(1_704_067_210_000));
+        assert_eq!(history[1].get_reason(), Some(RejectReason::OutOfOrder));
+        assert_eq!(history[2].get_reason(), None);
+        assert_eq!(c.get_available_funds(), 20.0);
+        let c = tp.clients.get(&2).unwrap();
+        assert_eq!(c.get_history()[1].get_timestamp(), None);
+        assert_eq!(c.get_available_funds(), 20.0);
+    }
+
+    #[test]
+    fn timestamps_rejected() {
+        let timed = |mut trans: Transaction, ts: Timestamp| {
+            trans.set_timestamp(Some(ts));
+            trans
+        };
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_transaction(timed(Transaction::new_deposit(1, 1, 10.0, false), 100));
+        tp.process_transaction(timed(Transaction::new_withdrawl(1, 2, 50.0, false), 900));
+        tp.process_transaction(timed(Transaction::new_transfer(1, 2, 3, 50.0, false), 900));
+        tp.process_transaction(timed(Transaction::new_deposit(1, 4, 10.0, false), 200));
+        tp.process_transaction(timed(Transaction::new_deposit(2, 5, 10.0, false), 200));
+
+        // the rejected rows didn't move either clock past the later deposits
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_history()[1].get_reason(), Some(RejectReason::InsufficientFunds));
+        assert_eq!(c.get_history()[3].get_reason(), None);
+        assert_eq!(c.get_last_timestamp(), Some(200));
+        assert_eq!(c.get_available_funds(), 20.0);
+        assert_eq!(tp.clients.get(&2).unwrap().get_available_funds(), 10.0);
+    }
+
+    #[test]
+    fn timestamps_reorder() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, 100\n\
+             withdrawal, 1, 3, 15.0, 103\n\
+             deposit, 1, 2, 10.0, 101\n\
+             deposit, 1, 4, 1.0, 110\n\
+             withdrawal, 1, 5, 1.0, 102\n\
+             deposit, 1, 6, 1.0,";
+
+        let mut config = Config::default();
+        config.timestamps.reorder_tolerance = 5.0;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        let c = tp.clients.get(&1).unwrap();
+        let order: Vec<(TransactionID,Option<RejectReason>)> = c.get_history().iter()
+            .map(|e| (e.get_id(), e.get_reason())).collect();
+        assert_eq!(order, vec![
+            (1, None), (2, None), (3, None), (5, Some(RejectReason::OutOfOrder)),
+            (4, None), (6, None)
+        ]);
+        assert_eq!(c.get_available_funds(), 7.0);
+    }
+
+    #[test]
+    fn timestamps_invalid() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, yesterday";
+
+        let mut tp = TransactionProcessor::new();
+        let res = tp.process_csv_stream(input.as_bytes());
+
+        assert!(matches!(res, Err(TransactionProcessorErr::CSVError(_))));
+    }
+
+    #[test]
+    fn dispute_window() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, 1000\n\
+             deposit, 1, 2, 10.0, 1050\n\
+             dispute, 1, 1,, 1101\n\
+             dispute, 1, 2,, 1101\n\
+             deposit, 1, 3, 10.0,\n\
+             dispute, 1, 3,,";
+
+        let mut config = Config::default();
+        config.policy.dispute_window = Some(100.0);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        let c = tp.clients.get(&1).unwrap();
+        let history = c.get_history();
+        assert_eq!(history[2].get_reason(), Some(RejectReason::DisputeWindowClosed));
+        assert_eq!(history[3].get_reason(), None);
+        // transactions without timestamps can always be disputed
+        assert_eq!(history[5].get_reason(), None);
+        assert_eq!(c.get_held_funds(), 20.0);
+    }
+
+    #[test]
+    fn dispute_expiry() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, 1000\n\
+             deposit, 1, 2, 10.0, 1000\n\
+             dispute, 1, 1,, 1010\n\
+             dispute, 1, 2,, 1050\n\
+             deposit, 1, 3, 1.0, 1071\n\
+             resolve, 1, 1,, 1072";
+
+        let mut config = Config::default();
+        config.policy.dispute_deadline = Some(60.0);
+        config.policy.on_dispute_expiry = ExpiryAction::Chargeback;
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        let c = tp.clients.get(&1).unwrap();
+        let history = c.get_history();
+        // the first dispute expired at 1070, before the deposit at 1071
+        assert_eq!(history[4].get_id(), 1);
+        assert_eq!(history[4].get_type(), TransactionType::Chargeback);
+        assert_eq!(history[4].get_timestamp(), Some(1_070_000));
+        assert!(history[4].is_synthetic());
+        assert_eq!(history[5].get_id(), 3);
+        assert_eq!(history[6].get_reason(), Some(RejectReason::AccountLocked));
+        assert!(c.is_locked());
+        assert_eq!(c.get_held_funds(), 10.0);
 
-        Ok(())
+        tp.sweep_disputes(1_110_000);
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_history().len(), 7);
+        tp.sweep_disputes(1_110_001);
+        let c = tp.clients.get(&1).unwrap();
+        let history = c.get_history();
+        // the second expiry applies even though the account was locked
+        assert_eq!(history.len(), 8);
+        assert!(history[7].is_synthetic());
+        assert_eq!(history[7].get_reason(), None);
+        assert_eq!(c.get_held_funds(), 0.0);
+
+        tp.sweep_disputes(1_200_000);
+        assert_eq!(tp.clients.get(&1).unwrap().get_history().len(), 8);
     }
 
-    /// Process a single transaction
-    ///
-    /// Note: A client will be created if one does not already exist
-    pub fn process_transaction(&mut self, trans: Transaction) {
-        // add client if client doesn't exist
-        if let None = self.clients.get(&trans.get_client_id()) {
-            let client = Client::new(trans.get_client_id());
-            self.clients.insert(trans.get_client_id(), client);
-        }
+    #[test]
+    fn dispute_expiry_resolve() {
+        let mut config = Config::default();
+        config.policy.dispute_deadline = Some(60.0);
 
-        // we just added the client if it didn't exist so unwrap shouldn't
-        //  panic here
-        let client = self.clients.get_mut(&trans.get_client_id()).unwrap();
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        let mut deposit = Transaction::new_deposit(1, 1, 10.0, false);
+        deposit.set_timestamp(Some(0));
+        let mut dispute = Transaction::new_dispute(1, 1);
+        dispute.set_timestamp(Some(1_000));
+        tp.process_transaction(deposit);
+        tp.process_transaction(dispute);
+        tp.sweep_disputes(100_000);
 
-        // within this match calls to get_amount are unwraped because we know
-        //  at those times that it is Some bacause of where the transaction
-        //  came from or what type of transaction it is
-        use crate::transaction::TransactionType::*;
-        match trans.get_type() {
-            // add funds to client and record transaction
-            Deposit => {
-                client.add_funds(trans.get_amount().unwrap());
-                self.transactions.insert(trans.get_id(), trans);
-            },
-            // remove funds from client and record transaction if remove was
-            //  possible
-            Withdrawal => {
-                if client.remove_funds(trans.get_amount().unwrap()) {
-                    self.transactions.insert(trans.get_id(), trans);
-                }
-            },
-            // if disputed transaction was found hold funds from client
-            Dispute => {
-                if let Some(trans_other) = self.transactions.get_mut(&trans.get_id()) {
-                    client.hold_funds(trans_other.get_amount().unwrap());
-                    trans_other.set_disputed();
-                }
-            },
-            // if disputed transaction was found and is in dispute
-            //  restore held funds to client
-            Resolve => {
-                if let Some(trans_other) = self.transactions.get_mut(&trans.get_id()) {
-                    if trans_other.is_disputed() {
-                        client.restore_funds(trans_other.get_amount().unwrap());
-                        trans_other.clear_disputed();
-                    }
-                }
-            },
-            // if disputed transaction was found and is in dispute
-            //  remove held function from client and lock client
-            Chargeback => {
-                if let Some(trans_other) = self.transactions.get_mut(&trans.get_id()) {
-                    if trans_other.is_disputed() {
-                        client.restore_funds(trans_other.get_amount().unwrap());
-                        client.remove_funds(trans_other.get_amount().unwrap());
-                        client.lock();
-                        trans_other.clear_disputed();
-                    }
-                }
-            }
-        };
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_available_funds(), 10.0);
+        assert!(!tp.transactions.get(&1).unwrap().is_disputed());
     }
 
-    /// Export Client info in CSV format
-    pub fn write_csv_to_stream<W>(&self, writer: W) -> Result<(),TransactionProcessorErr>
-            where W: io::Write {
-        use TransactionProcessorErr::*;
+    #[test]
+    fn dispute_expiry_locked() {
+        let input =
+            "type, client, tx, amount, timestamp\n\
+             deposit, 1, 1, 10.0, 0\n\
+             deposit, 1, 2, 5.0, 0\n\
+             dispute, 1, 1,, 1\n\
+             dispute, 1, 2,, 2\n\
+             chargeback, 1, 2,, 3";
 
-        let mut csv_writer = csv::Writer::from_writer(writer);
+        let mut config = Config::default();
+        config.policy.dispute_deadline = Some(60.0);
 
-        for c in self.clients.values() {
-            csv_writer.serialize(c).map_err(|e| CSVError(e))?;
-        }
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_checked(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+        assert!(tp.clients.get(&1).unwrap().is_locked());
 
-        Ok(())
+        tp.sweep_disputes(100_000);
+        let c = tp.clients.get(&1).unwrap();
+        assert!(c.is_locked());
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(c.get_available_funds(), 10.0);
+        assert!(!tp.transactions.get(&1).unwrap().is_disputed());
     }
 
-    /// Print Client list to stdout
-    #[allow(dead_code)]
-    pub fn print_clients(&self) {
-        for c in self.clients.values() {
-            println!("{:?}",c);
-        }
+    #[test]
+    fn dispute_expiry_retry() {
+        let mut config = Config::default();
+        config.policy.dispute_deadline = Some(60.0);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_record_history(true);
+        let mut deposit = Transaction::new_deposit(1, 1, 10.0, false);
+        deposit.set_timestamp(Some(0));
+        let mut dispute = Transaction::new_dispute(1, 1);
+        dispute.set_timestamp(Some(1_000));
+        tp.process_transaction(deposit);
+        tp.process_transaction(dispute);
+
+        // the resolve can't release funds that are no longer held
+        tp.clients.get_mut(&1).unwrap().remove_held_funds(10.0);
+        tp.sweep_disputes(100_000);
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_history()[2].get_reason(), Some(RejectReason::InsufficientFunds));
+        assert!(tp.transactions.get(&1).unwrap().is_disputed());
+
+        tp.clients.get_mut(&1).unwrap().hold_funds(10.0);
+        let mut deposit = Transaction::new_deposit(1, 2, 1.0, false);
+        deposit.set_timestamp(Some(200_000));
+        tp.process_transaction(deposit);
+        let c = tp.clients.get(&1).unwrap();
+        let history = c.get_history();
+        assert!(history[3].is_synthetic());
+        assert_eq!(history[3].get_reason(), None);
+        assert_eq!(history[3].get_timestamp(), Some(61_000));
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert!(!tp.transactions.get(&1).unwrap().is_disputed());
+        assert!(tp.deadlines.is_empty());
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::client::*;
-    use crate::transaction::*;
+    #[test]
+    fn dispute_expiry_out_of_order() {
+        let input =
+            "type, client, tx, amount, counterparty, timestamp\n\
+             deposit, 1, 1, 10.0,, 0\n\
+             transfer, 1, 2, 5.0, 2, 10\n\
+             dispute, 1, 2,,, 20";
+
+        let mut config = Config::default();
+        config.policy.dispute_deadline = Some(60.0);
+
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.set_checked(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        // the expiry applies even though client 2's clock is past the deadline
+        tp.clients.get_mut(&2).unwrap().set_last_timestamp(90_000);
+        tp.sweep_disputes(100_000);
+        assert_eq!(tp.clients.get(&2).unwrap().get_last_timestamp(), Some(90_000));
+        assert!(!tp.transactions.get(&2).unwrap().is_disputed());
+        assert_eq!(tp.clients.get(&1).unwrap().get_available_funds(), 5.0);
+        assert_eq!(tp.clients.get(&2).unwrap().get_held_funds(), 0.0);
+    }
 
     #[test]
-    fn in_out_basic() {
+    fn opening_balances() {
+        let opening =
+            "client,available,held,total,locked,status,receivable,credit_used\n\
+             1,12.5000,0.0000,12.5000,false,active,0.0000,0.0000\n\
+             2,5.0000,0.0000,5.0000,true,locked,1.5000,0.0000\n";
         let input =
             "type, client, tx, amount\n\
              deposit, 1, 1, 1.0\n\
-             deposit, 1, 3, 2.0\n\
-             withdrawal, 1, 4, 1.5";
-        let expected_out =
-            "client,available,held,total,locked\n\
-             1,1.5000,0.0000,1.5000,false\n";
+             withdrawal, 1, 2, 11.5\n\
+             deposit, 2, 3, 1.0\n\
+             deposit, 3, 4, 1.0";
 
-        let mut out_buf = Vec::new();
         let mut tp = TransactionProcessor::new();
+        tp.load_opening_balances(opening.as_bytes()).unwrap();
         tp.process_csv_stream(input.as_bytes()).unwrap();
-        tp.write_csv_to_stream(&mut out_buf).unwrap();
-        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
 
-        assert_eq!(out, expected_out);
+        let c1 = tp.clients.get(&1).unwrap();
+        assert_eq!(c1.get_available_funds(), 2.0);
+        assert_eq!(c1.get_held_funds(), 0.0);
+        assert_eq!(c1.get_total_funds(), 2.0);
+        let c2 = tp.clients.get(&2).unwrap();
+        assert!(c2.is_locked());
+        assert_eq!(c2.get_total_funds(), 5.0);
+        assert_eq!(c2.get_receivable(), 1.5);
+        assert_eq!(tp.clients.get(&3).unwrap().get_total_funds(), 1.0);
     }
 
     #[test]
-    fn in_out_resolve() {
-        let input =
+    fn opening_balances_chained() {
+        let day1 =
             "type, client, tx, amount\n\
-             deposit, 1, 1, 100.0\n\
-             dispute, 1, 1,\n\
-             resolve, 1, 1,";
-        let expected_out =
-            "client,available,held,total,locked\n\
-             1,100.0000,0.0000,100.0000,false\n";
+             deposit, 1, 1, 10.0\n\
+             deposit, 2, 2, 3.0\n\
+             dispute, 2, 2,\n\
+             chargeback, 2, 2,";
+        let day2 =
+            "type, client, tx, amount\n\
+             withdrawal, 1, 3, 4.0\n\
+             deposit, 2, 4, 1.0";
 
-        let mut out_buf = Vec::new();
         let mut tp = TransactionProcessor::new();
-        tp.process_csv_stream(input.as_bytes()).unwrap();
-        tp.write_csv_to_stream(&mut out_buf).unwrap();
-        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
+        tp.process_csv_stream(day1.as_bytes()).unwrap();
+        let mut closing = Vec::new();
+        tp.write_csv_to_stream(&mut closing).unwrap();
 
-        assert_eq!(out, expected_out);
+        let mut tp = TransactionProcessor::new();
+        tp.load_opening_balances(closing.as_slice()).unwrap();
+        tp.process_csv_stream(day2.as_bytes()).unwrap();
+
+        assert_eq!(tp.clients.get(&1).unwrap().get_available_funds(), 6.0);
+        // client 2 is still locked from the chargeback
+        assert!(tp.clients.get(&2).unwrap().is_locked());
+        assert_eq!(tp.clients.get(&2).unwrap().get_total_funds(), 0.0);
     }
 
     #[test]
-    fn in_out_chargeback() {
+    fn opening_balances_invalid() {
+        use TransactionProcessorErr::*;
+
+        let mut tp = TransactionProcessor::new();
+        let opening = "client,available,held,total,locked\n1,10.0,2.0,11.0,false";
+        assert!(matches!(tp.load_opening_balances(opening.as_bytes()),
+                         Err(ClientRecordValidateError(_))));
+
+        let opening = "client,available,held,total,locked\n1,10.0,0.0,10.0,false\n\
+                       1,1.0,0.0,1.0,false";
+        assert!(matches!(tp.load_opening_balances(opening.as_bytes()),
+                         Err(ClientRecordValidateError(_))));
+
+        // nothing could release opening held funds
+        let opening = "client,available,held,total,locked\n2,10.0,2.0,12.0,false";
+        assert!(matches!(tp.load_opening_balances(opening.as_bytes()),
+                         Err(ClientRecordValidateError(_))));
+    }
+
+    #[test]
+    fn get_records() {
         let input =
             "type, client, tx, amount\n\
-             deposit, 1, 1, 100.0\n\
-             dispute, 1, 1,\n\
-             chargeback, 1, 1,";
-        let expected_out =
-            "client,available,held,total,locked\n\
-             1,0.0000,0.0000,0.0000,true\n";
+             deposit, 3, 1, 1.0\n\
+             deposit, 1, 2, 2.0\n\
+             deposit, 2, 3, 3.0";
 
-        let mut out_buf = Vec::new();
         let mut tp = TransactionProcessor::new();
         tp.process_csv_stream(input.as_bytes()).unwrap();
-        tp.write_csv_to_stream(&mut out_buf).unwrap();
-        let out = std::str::from_utf8(out_buf.as_slice()).unwrap().to_string();
 
-        assert_eq!(out, expected_out);
+        let records = tp.get_records();
+        let ids: Vec<ClientID> = records.iter().map(|r| r.client).collect();
+        assert_eq!(ids, vec![1,2,3]);
+        assert_eq!(records[1].total, 3.0);
     }
 
     #[test]
-    fn bad_input1() {
+    fn checked() {
+        let opening =
+            "client,available,held,total,locked\n\
+             4,5.0,0.0,5.0,false";
         let input =
-            "type, client, tx, amount\n\
-             deposit, 1, 1, 1.0\n\
-             deposit, 1, 3,\n\
-             withdrawal, 1, 4, 1.5";
+            "type, client, tx, amount, counterparty\n\
+             deposit, 1, 1, 10.0,\n\
+             deposit, 2, 2, 5.0,\n\
+             transfer, 1, 3, 4.0, 2\n\
+             withdrawal, 2, 4, 8.0,\n\
+             dispute, 1, 3, 2.0,\n\
+             dispute, 1, 1,,\n\
+             authorize, 3, 5, 1.0,\n\
+             deposit, 3, 6, 3.0,\n\
+             authorize, 3, 7, 2.0,\n\
+             capture, 3, 7, 0.5,\n\
+             resolve, 1, 3,,\n\
+             chargeback, 1, 1, 4.0,\n\
+             deposit, 4, 8, 1.0,";
+
+        let mut config = Config::default();
+        config.policy.negative_balance = NegativeBalanceMode::Cap;
 
         let mut tp = TransactionProcessor::new();
-        let result = tp.process_csv_stream(input.as_bytes()).unwrap_err();
-        match result {
-            TransactionProcessorErr::TransactionValidateError(_) => (),
-            _ => panic!("incorrect result")
-        }
+        tp.set_config(config);
+        tp.set_checked(true);
+        tp.load_opening_balances(opening.as_bytes()).unwrap();
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        assert_eq!(tp.clients.get(&3).unwrap().get_held_funds(), 1.5);
+        assert!((tp.get_expected_held(1).0 - 3.6).abs() < 1e-9);
+        assert!((tp.clients.get(&1).unwrap().get_held_funds() - 3.6).abs() < 1e-9);
+        assert_eq!(tp.get_expected_held(4), (0.0, 1.0));
     }
 
     #[test]
-    fn bad_input2() {
+    fn checked_running_held() {
         let input =
-            "type, client, tx, amount\n\
-             deposit, 1, 1, 1.0\n\
-             deposit,\n\
-             withdrawal, 1, 4, 1.5";
+            "type, client, tx, amount, counterparty\n\
+             deposit, 1, 1, 10.0,\n\
+             dispute, 1, 1,,\n\
+             authorize, 1, 2, 3.0,\n\
+             resolve, 1, 1,,\n\
+             authorize, 1, 2, 3.0,\n\
+             authorize, 1, 3, 2.0,\n\
+             void, 1, 2,,\n\
+             capture, 1, 3, 1.0,";
 
         let mut tp = TransactionProcessor::new();
-        let result = tp.process_csv_stream(input.as_bytes()).unwrap_err();
-        match result {
-            TransactionProcessorErr::CSVError(_) => (),
-            _ => panic!("incorrect result")
-        }
+        tp.set_checked(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
+
+        assert_eq!(tp.get_expected_held(1), (1.0, 15.0));
+        assert_eq!(tp.clients.get(&1).unwrap().get_held_funds(), 1.0);
     }
 
     #[test]
-    fn deposit() {
-        let c_id = 500;
-        let t_id = 600;
-        let amount = 100.0;
+    fn checked_turned_on_late() {
+        let opening =
+            "client,available,held,total,locked\n\
+             1,2.0,0.0,2.0,false";
 
         let mut tp = TransactionProcessor::new();
-        let t = Transaction::new_deposit(c_id, t_id, amount, false);
+        tp.load_opening_balances(opening.as_bytes()).unwrap();
+        tp.process_transaction(Transaction::new_deposit(1, 1, 10.0, false));
+        tp.process_transaction(Transaction::new_dispute(1, 1));
+        assert!(tp.expected_held.is_empty());
 
-        tp.process_transaction(t);
+        tp.set_checked(true);
+        assert_eq!(tp.get_expected_held(1), (10.0, 10.0));
+        tp.process_transaction(Transaction::new_resolve(1, 1));
+        assert_eq!(tp.get_expected_held(1), (0.0, 10.0));
 
-        let c = tp.clients.get(&c_id);
-        assert!(c.is_some());   // ensure client was created
-        assert_eq!(c.unwrap().get_available_funds(),amount);
-        assert!(tp.transactions.get(&t_id).is_some());
+        // opening balances replace what the client had
+        tp.load_opening_balances(opening.as_bytes()).unwrap();
+        assert_eq!(tp.get_expected_held(1), (0.0, 0.0));
+        tp.process_transaction(Transaction::new_deposit(1, 2, 1.0, false));
+        assert_eq!(tp.get_expected_held(1), (0.0, 1.0));
     }
 
     #[test]
-    fn withdrawal() {
-        let c_id = 500;
-        let t_id = 600;
-        let amount = 100.0;
-        let wothdraw_amount = 10.0;
-
+    #[should_panic(expected = "invariant broken for client 1")]
+    fn checked_violation() {
         let mut tp = TransactionProcessor::new();
-        let mut c = Client::new(c_id);
-        c.add_funds(amount);
-        tp.clients.insert(c_id, c);
+        tp.set_checked(true);
+        tp.process_transaction(Transaction::new_deposit(1, 1, 10.0, false));
+        tp.clients.get_mut(&1).unwrap().hold_funds(1.0);
+        tp.process_transaction(Transaction::new_deposit(1, 2, 10.0, false));
+    }
 
-        let t = Transaction::new_withdrawl(c_id, t_id, wothdraw_amount, false);
+    #[test]
+    fn dispute_other_client() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 10.0\n\
+             deposit, 2, 2, 10.0\n\
+             dispute, 2, 1,\n\
+             chargeback, 2, 1,";
 
-        tp.process_transaction(t);
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
 
-        let ec = tp.clients.get(&c_id).unwrap();
-        assert_eq!(ec.get_available_funds(),amount-wothdraw_amount);
+        let c = tp.clients.get(&2).unwrap();
+        assert_eq!(c.get_history()[1].get_reason(), Some(RejectReason::UnknownTransaction));
+        assert_eq!(c.get_history()[2].get_reason(), Some(RejectReason::UnknownTransaction));
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert!(!tp.transactions.get(&1).unwrap().is_disputed());
     }
 
     #[test]
-    fn dispute() {
-        let c_id = 500;
-        let t_id = 600;
-        let amount = 100.0;
+    fn invalid_transaction() {
+        use TransactionType::*;
 
         let mut tp = TransactionProcessor::new();
-        let c = Client::new(c_id);
-        tp.clients.insert(c_id, c);
+        tp.set_record_history(true);
+        tp.process_transaction(Transaction::new_admin(Deposit, 1, 1, None, None));
+        tp.process_transaction(Transaction::new_authorize(1, 2, -5.0));
 
-        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
-        let t2 = Transaction::new_dispute(c_id, t_id);
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_history()[0].get_reason(), Some(RejectReason::InvalidTransaction));
+        assert_eq!(c.get_history()[1].get_reason(), Some(RejectReason::InvalidTransaction));
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert!(tp.transactions.is_empty());
+    }
 
-        tp.process_transaction(t1);
-        tp.process_transaction(t2);
+    #[test]
+    fn duplicate_transaction() {
+        let input =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 10.0\n\
+             dispute, 1, 1,\n\
+             deposit, 1, 1, 5.0\n\
+             withdrawal, 2, 1, 5.0\n\
+             resolve, 1, 1,";
 
-        let ec = tp.clients.get(&c_id).unwrap();
-        assert_eq!(ec.get_held_funds(),amount);
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        tp.process_csv_stream(input.as_bytes()).unwrap();
 
-        let et = tp.transactions.get(&t_id).unwrap();
-        assert!(et.is_disputed());
+        let c = tp.clients.get(&1).unwrap();
+        assert_eq!(c.get_history()[2].get_reason(), Some(RejectReason::DuplicateTransaction));
+        assert_eq!(c.get_history()[3].get_reason(), None);
+        assert_eq!(c.get_available_funds(), 10.0);
+        assert_eq!(c.get_held_funds(), 0.0);
+        assert_eq!(tp.clients.get(&2).unwrap().get_history()[0].get_reason(),
+                   Some(RejectReason::DuplicateTransaction));
     }
 
     #[test]
-    fn resolve() {
-        let c_id = 500;
-        let t_id = 600;
-        let amount = 100.0;
+    fn fast_csv() {
+        use crate::generate::{write_workload,WorkloadConfig};
 
-        let mut tp = TransactionProcessor::new();
-        let c = Client::new(c_id);
-        tp.clients.insert(c_id, c);
+        let run = |input: &[u8], fast: bool| {
+            let mut tp = TransactionProcessor::new();
+            tp.set_allow_admin(true);
+            tp.set_fast_csv(fast);
+            let result = tp.process_csv_stream(input).map_err(|e| e.to_string());
+            (result, tp.get_records())
+        };
 
-        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
-        let t2 = Transaction::new_dispute(c_id, t_id);
-        let t3 = Transaction::new_resolve(c_id, t_id);
+        let mut input = Vec::new();
+        let config = WorkloadConfig { clients: 50, rows: 2_000, ..WorkloadConfig::default() };
+        write_workload(&config, &mut input).unwrap();
+        let (result, records) = run(&input, true);
+        assert_eq!(result, Ok(()));
+        assert_eq!(records, run(&input, false).1);
 
-        tp.process_transaction(t1);
-        tp.process_transaction(t2);
-        tp.process_transaction(t3);
+        // rows the fast path leaves to serde, and errors
+        let header = "type, client, tx, amount, counterparty, reason, timestamp\n";
+        for rows in ["deposit, 0x1, 1, 1e1,,, 2023-11-14T22:13:20Z\n\
+                      adjust, 1, 2, -1.5,, fee, 1700000000.5\n\
+                      transfer, 1, 3, 2,2,,\n",
+                     "deposit, 1, 1, 1.0,,,\nDeposit, 1, 2, 1.0,,,\n",
+                     "deposit, 1, 1, 1.0,,,\ndeposit, 65536, 2, 1.0,,,\n",
+                     "deposit, 1, 1, abc,,,\n",
+                     "deposit, 1, 1, -1.0,,,\n",
+                     "deposit, 1, 1,,,,\n",
+                     "deposit, 1, 1, 1.0,,, yesterday\n",
+                     "deposit, 1, 1, 1.0\n",
+                     "deposit, 1, 1, \u{a0}1.0\u{2003},,,\ndeposit, 1, 2, \x0b2.0,,,\n",
+                     "adjust, 1, 1, 1.0,, \"caf\u{e9}, \"\"fee\"\"\",\n"] {
+            let input = format!("{}{}", header, rows);
+            assert_eq!(run(input.as_bytes(), true), run(input.as_bytes(), false), "{}", rows);
+        }
+        for row in [&b"deposit, 1, 1, 1.0,, \xff,\n"[..], b"deposit, 1, 1, 1.0,,,\nnote\xff",
+                    b"deposit, 1, 1, 1.0,, \"\n\xc3\",\n"] {
+            let mut input = header.as_bytes().to_vec();
+            input.extend_from_slice(row);
+            assert_eq!(run(&input, true), run(&input, false));
+            assert!(run(&input, true).0.is_err());
+        }
 
-        let ec = tp.clients.get(&c_id).unwrap();
-        assert_eq!(ec.get_available_funds(),amount);
+        // headers the fast path can't use
+        for input in ["type, client, amount\ndeposit, 1, 1.0\n",
+                      "type, client, tx, tx\ndeposit, 1, 1, 2\n"] {
+            assert_eq!(run(input.as_bytes(), true), run(input.as_bytes(), false));
+        }
+    }
 
-        let et = tp.transactions.get(&t_id).unwrap();
-        assert!(!et.is_disputed());
+    #[test]
+    fn csv_bytes() {
+        use crate::generate::{write_workload,WorkloadConfig};
+
+        let stream = |input: &[u8]| {
+            let mut tp = TransactionProcessor::new();
+            tp.set_allow_admin(true);
+            let result = tp.process_csv_stream(input).map_err(|e| e.to_string());
+            (result, tp.get_records())
+        };
+        let chunked = |input: &[u8], chunk_size: usize| {
+            let mut tp = TransactionProcessor::new();
+            tp.set_allow_admin(true);
+            let result = tp.process_csv_bytes(input, chunk_size, 3, None).map_err(|e| e.to_string());
+            tp.flush_transactions();
+            (result, tp.get_records())
+        };
+
+        let mut input = Vec::new();
+        let config = WorkloadConfig { clients: 50, rows: 2_000, ..WorkloadConfig::default() };
+        write_workload(&config, &mut input).unwrap();
+        let expected = stream(&input);
+        assert_eq!(expected.0, Ok(()));
+        for chunk_size in [1, 100, 10_000, 1 << 20] {
+            assert_eq!(chunked(&input, chunk_size), expected);
+        }
+
+        // errors part way through are reported from where they are
+        let header = "type, client, tx, amount, reason\r\n";
+        for rows in ["deposit, 1, 1, 1.0,\r\n\r\ndeposit, 2, 2, 1.0,\r\nadjust, 1, 3, -0.5, fee\r\n",
+                     "deposit, 1, 1, 1.0,\ndeposit, 2, 2, 2.0,\ndeposit, 2, 3, 1.0\n",
+                     "deposit, 1, 1, 1.0,\ndeposit, 2, 2, 2.0,\ndeposit, 2, 3, x,\n",
+                     "deposit, 1, 1, 1.0,\ndeposit, 2, 2, 2.0,\ndeposit, 2, 3, -1.0,\n",
+                     "deposit, 1, 1, 1.0,\ndeposit, 2, 2, 2.0,\nadjust, 2, 3, -1.0, \"a\nb\"\n"] {
+            let input = format!("{}{}", header, rows);
+            let expected = stream(input.as_bytes());
+            for chunk_size in [1, 30, 1000] {
+                assert_eq!(chunked(input.as_bytes(), chunk_size), expected, "{}", rows);
+            }
+        }
+        assert_eq!(chunked(b"", 1), stream(b""));
+        assert_eq!(chunked(b"type,client\ndeposit,1\n", 1), stream(b"type,client\ndeposit,1\n"));
     }
 
     #[test]
-    fn chargeback() {
-        let c_id = 500;
-        let t_id = 600;
-        let amount = 100.0;
+    fn csv_file() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 1.0\n\
+                     deposit, 2, 2, 2.0";
+        let path = std::env::temp_dir().join(format!("csv_file_{}.csv", std::process::id()));
+        std::fs::write(&path, input).unwrap();
 
         let mut tp = TransactionProcessor::new();
-        let c = Client::new(c_id);
-        tp.clients.insert(c_id, c);
+        tp.process_csv_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tp.get_records().len(), 2);
+        assert!(tp.process_csv_file(&path).is_err());
+    }
 
-        let t1 = Transaction::new_deposit(c_id, t_id, amount, false);
-        let t2 = Transaction::new_dispute(c_id, t_id);
-        let t3 = Transaction::new_chargeback(c_id, t_id);
+    #[test]
+    fn source_lines() {
+        let lines = |tp: &TransactionProcessor| -> Vec<u64> {
+            tp.get_client(1).unwrap().get_history().iter()
+                .map(|e| e.get_source().unwrap().line)
+                .collect()
+        };
 
-        tp.process_transaction(t1);
-        tp.process_transaction(t2);
-        tp.process_transaction(t3);
+        // rows are attributed to the line they start on however lines end,
+        //  whether read as a stream or in chunks
+        let quoted = "type,client,tx,amount,reason\n\ndeposit,1,1,1.0,\ndeposit,1,2,1.0,\n\n\n\
+                      adjust,1,3,1.0,\"a\nb\"\ndeposit,1,4,1.0,";
+        let plain = "type,client,tx,amount,reason\n\ndeposit,1,1,1.0,\ndeposit,1,2,1.0,\n\n\n\
+                     deposit,1,4,1.0,\n";
+        let input: Arc<str> = "in.csv".into();
+        for (rows, expected) in [(quoted, vec![3, 4, 7, 9]), (plain, vec![3, 4, 7])] {
+            for rows in [rows.to_string(), rows.replace('\n', "\r\n")] {
+                for fast in [true, false] {
+                    let mut tp = TransactionProcessor::new();
+                    tp.set_record_history(true);
+                    tp.set_allow_admin(true);
+                    tp.set_fast_csv(fast);
+                    let mut csv_rows = tp.csv_rows(rows.as_bytes());
+                    tp.process_csv_rows(&mut csv_rows, Some(&input)).unwrap();
+                    assert_eq!(lines(&tp), expected, "{:?}", rows);
+                }
+                for chunk_size in [1, 20, 1000] {
+                    let mut tp = TransactionProcessor::new();
+                    tp.set_record_history(true);
+                    tp.set_allow_admin(true);
+                    tp.process_csv_bytes(rows.as_bytes(), chunk_size, 3, Some(&input)).unwrap();
+                    assert_eq!(lines(&tp), expected, "{:?}", rows);
+                }
+            }
+        }
 
-        let ec = tp.clients.get(&c_id).unwrap();
-        assert_eq!(ec.get_available_funds(),0.0);
-        assert!(ec.is_locked());
+        // errors are attributed to the line they're on
+        let rows = "type,client,tx,amount\r\ndeposit,1,1,1.0\r\n\r\ndeposit,1,2,x\r\n";
+        for chunk_size in [1, 1000] {
+            let mut tp = TransactionProcessor::new();
+            let err = tp.process_csv_bytes(rows.as_bytes(), chunk_size, 3, Some(&input))
+                .unwrap_err().to_string();
+            assert!(err.starts_with("in.csv:4: CSV error"), "{}", err);
+        }
+        let mut tp = TransactionProcessor::new();
+        let err = tp.process_csv_bytes(b"type,client,tx,amount\ndeposit,1,1,-1.0\n", 1, 3,
+                                       Some(&input)).unwrap_err().to_string();
+        assert!(err.starts_with("in.csv:2: invalid transaction"), "{}", err);
+    }
 
-        let et = tp.transactions.get(&t_id).unwrap();
-        assert!(!et.is_disputed());
+    #[test]
+    fn csv_files() {
+        let dir = std::env::temp_dir().join(format!("csv_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, rows: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("type,client,tx,amount,timestamp\n{}", rows)).unwrap();
+            path
+        };
+        let a = file("a.csv", "deposit,1,1,1.0,10\ndeposit,1,3,1.0,30\n");
+        let b = file("b.csv", "deposit,1,2,1.0,20\ndeposit,1,4,1.0,\n");
+        let c = file("c.csv", "deposit,2,5,1.0,15\ndeposit,2,6,x,25\n");
+        let missing = dir.join("missing.csv");
+        let process = |paths: &[&PathBuf], order: InputOrder| {
+            let mut tp = TransactionProcessor::new();
+            tp.set_record_history(true);
+            let result = tp.process_csv_files(paths, order).map_err(|e| e.to_string());
+            let sources: Vec<String> = tp.get_client(1).map_or(vec![], |c| {
+                c.get_history().iter().map(|e| e.get_source().unwrap().to_string()).collect()
+            });
+            (result, sources)
+        };
+        let source = |path: &PathBuf, line: u64| format!("{}:{}", path.display(), line);
+
+        assert_eq!(process(&[&b, &a], InputOrder::Listed),
+                   (Ok(()), vec![source(&b, 2), source(&b, 3), source(&a, 2), source(&a, 3)]));
+        assert_eq!(process(&[&b, &a], InputOrder::Name),
+                   (Ok(()), vec![source(&a, 2), source(&a, 3), source(&b, 2), source(&b, 3)]));
+        // a row without a timestamp follows the one before it in its file
+        assert_eq!(process(&[&b, &a], InputOrder::Timestamp),
+                   (Ok(()), vec![source(&a, 2), source(&b, 2), source(&b, 3), source(&a, 3)]));
+
+        // an error stops processing where its row would have been processed
+        let (result, sources) = process(&[&a, &c], InputOrder::Timestamp);
+        assert!(result.unwrap_err().starts_with(&format!("{}: CSV error", source(&c, 3))));
+        assert_eq!(sources, vec![source(&a, 2)]);
+        let (result, sources) = process(&[&a, &missing], InputOrder::Listed);
+        assert!(result.unwrap_err().starts_with(&format!("{}: IO error", missing.display())));
+        assert_eq!(sources.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
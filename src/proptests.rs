@@ -0,0 +1,221 @@
+//! Property tests of the processing engine on generated transaction sequences
+
+use crate::types::*;
+use crate::config::Config;
+use crate::history::RejectReason;
+use crate::processor::TransactionProcessor;
+use crate::status::AccountStatus;
+use crate::transaction::{Transaction,TransactionType};
+
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+
+/// Clients the generated transactions are for
+const CLIENTS: ClientID = 3;
+/// Most operations in a generated sequence
+const MAX_OPS: usize = 40;
+
+/// A generated operation
+///
+/// Deposits and withdrawals get the ID of their position in the sequence so
+/// disputes can refer to them, to one of another client, to a dispute or to
+/// nothing at all
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Deposit(ClientID, u32),
+    Withdrawal(ClientID, u32),
+    Dispute(ClientID, TransactionID),
+    Resolve(ClientID, TransactionID),
+    Chargeback(ClientID, TransactionID)
+}
+
+impl Op {
+    /// Get the transaction for the operation at a position in the sequence
+    fn to_transaction(self, i: usize) -> Transaction {
+        let id = i as TransactionID + 1;
+        match self {
+            Op::Deposit(c, cents) => Transaction::new_deposit(c, id, amount(cents), false),
+            Op::Withdrawal(c, cents) => Transaction::new_withdrawl(c, id, amount(cents), false),
+            Op::Dispute(c, tx) => Transaction::new_dispute(c, tx),
+            Op::Resolve(c, tx) => Transaction::new_resolve(c, tx),
+            Op::Chargeback(c, tx) => Transaction::new_chargeback(c, tx)
+        }
+    }
+
+    /// Get the CSV row for the operation at a position in the sequence
+    fn to_csv(self, i: usize) -> String {
+        let id = i as TransactionID + 1;
+        match self {
+            Op::Deposit(c, cents) => format!("deposit,{},{},{}", c, id, amount(cents)),
+            Op::Withdrawal(c, cents) => format!("withdrawal,{},{},{}", c, id, amount(cents)),
+            Op::Dispute(c, tx) => format!("dispute,{},{},", c, tx),
+            Op::Resolve(c, tx) => format!("resolve,{},{},", c, tx),
+            Op::Chargeback(c, tx) => format!("chargeback,{},{},", c, tx)
+        }
+    }
+}
+
+/// Get an amount in whole cents
+fn amount(cents: u32) -> f64 {
+    cents as f64 / 100.0
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let client = 1..=CLIENTS;
+    let cents = 1..10_000u32;
+    let tx = 1..=(MAX_OPS as TransactionID + 5);
+    prop_oneof![
+        3 => (client.clone(), cents.clone()).prop_map(|(c, a)| Op::Deposit(c, a)),
+        2 => (client.clone(), cents).prop_map(|(c, a)| Op::Withdrawal(c, a)),
+        2 => (client.clone(), tx.clone()).prop_map(|(c, t)| Op::Dispute(c, t)),
+        1 => (client.clone(), tx.clone()).prop_map(|(c, t)| Op::Resolve(c, t)),
+        1 => (client, tx).prop_map(|(c, t)| Op::Chargeback(c, t))
+    ]
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    prop::collection::vec(op(), 0..MAX_OPS)
+}
+
+/// Process operations one at a time with invariant checking on
+fn run(ops: &[Op]) -> TransactionProcessor {
+    let mut tp = TransactionProcessor::new();
+    tp.set_record_history(true);
+    tp.set_checked(true);
+    for (i, op) in ops.iter().enumerate() {
+        tp.process_transaction(op.to_transaction(i));
+    }
+    tp
+}
+
+/// Get the CSV input for operations, with a timestamp column of 10 seconds
+/// apart if timestamped
+fn to_csv(ops: &[Op], timestamped: bool) -> String {
+    let mut csv = String::from("type,client,tx,amount");
+    csv.push_str(if timestamped { ",timestamp\n" } else { "\n" });
+    for (i, op) in ops.iter().enumerate() {
+        csv.push_str(&op.to_csv(i));
+        if timestamped {
+            csv.push_str(&format!(",{}", i * 10));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    /// Money only enters through deposits and leaves through withdrawals and
+    /// chargebacks
+    #[test]
+    fn conservation(ops in ops()) {
+        let tp = run(&ops);
+
+        let amounts: HashMap<TransactionID,f64> = ops.iter().enumerate()
+            .filter_map(|(i, op)| match op {
+                Op::Deposit(_, cents) | Op::Withdrawal(_, cents) => {
+                    Some((i as TransactionID + 1, amount(*cents)))
+                },
+                _ => None
+            })
+            .collect();
+
+        let mut expected = 0.0;
+        let mut total = 0.0;
+        for id in 1..=CLIENTS {
+            let client = match tp.get_client(id) {
+                Some(client) => client,
+                None => continue
+            };
+            total += client.get_total_funds();
+            for e in client.get_history().iter().filter(|e| e.get_reason().is_none()) {
+                let amount = amounts[&e.get_id()];
+                expected += match e.get_type() {
+                    TransactionType::Deposit => amount,
+                    TransactionType::Withdrawal | TransactionType::Chargeback => -amount,
+                    _ => 0.0
+                };
+            }
+        }
+        prop_assert!((total - expected).abs() < 1e-6, "total {} != {}", total, expected);
+    }
+
+    /// Held funds are never negative and always match the open disputes
+    ///
+    /// Note: The invariants are checked after every transaction by run
+    #[test]
+    fn no_negative_held(ops in ops()) {
+        let tp = run(&ops);
+        for r in tp.get_records() {
+            prop_assert!(r.held >= -1e-9, "client {} held {}", r.client, r.held);
+        }
+    }
+
+    /// A locked account's balances only change through operations the config
+    /// allows on locked accounts
+    #[test]
+    fn locked_unchanged(ops in ops()) {
+        let config = Config::default();
+        let mut tp = TransactionProcessor::new();
+        tp.set_record_history(true);
+        for (i, op) in ops.iter().enumerate() {
+            let trans = op.to_transaction(i);
+            let (id, typ) = (trans.get_client_id(), trans.get_type());
+            let before = tp.get_client(id).map(|c| (c.get_status(), c.to_record(false)));
+            tp.process_transaction(trans);
+
+            if let Some((AccountStatus::Locked, before)) = before {
+                let client = tp.get_client(id).unwrap();
+                if !config.allows(AccountStatus::Locked, typ) {
+                    prop_assert_eq!(client.to_record(false), before);
+                    let last = client.get_history().last().unwrap();
+                    prop_assert_eq!(last.get_reason(), Some(RejectReason::AccountLocked));
+                }
+            }
+        }
+    }
+
+    /// Processing one transaction at a time, from CSV, from CSV parsed in
+    /// chunks, with invariant checking and through the reorder buffer all give
+    /// the same balances
+    #[test]
+    fn modes_equivalent(ops in ops(), swaps in prop::collection::vec(any::<bool>(), MAX_OPS),
+                        chunk_size in 1..200usize) {
+        let expected = run(&ops).get_records();
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_stream(to_csv(&ops, false).as_bytes()).unwrap();
+        prop_assert_eq!(tp.get_records(), expected.clone());
+
+        let mut tp = TransactionProcessor::new();
+        tp.process_csv_bytes(to_csv(&ops, false).as_bytes(), chunk_size, 3, None).unwrap();
+        tp.flush_transactions();
+        prop_assert_eq!(tp.get_records(), expected.clone());
+
+        let mut tp = TransactionProcessor::new();
+        for (i, op) in ops.iter().enumerate() {
+            tp.process_transaction(op.to_transaction(i));
+        }
+        prop_assert_eq!(tp.get_records(), expected.clone());
+
+        // swapping neighbours delivers transactions up to 10 seconds late,
+        //  which the reorder buffer puts back in order
+        let mut lines: Vec<String> = to_csv(&ops, true).lines().map(String::from).collect();
+        let mut i = 1;
+        while i + 1 < lines.len() {
+            if swaps[i] {
+                lines.swap(i, i + 1);
+                i += 1;
+            }
+            i += 1;
+        }
+        let mut config = Config::default();
+        config.timestamps.reorder_tolerance = 15.0;
+        let mut tp = TransactionProcessor::new();
+        tp.set_config(config);
+        tp.process_csv_stream(lines.join("\n").as_bytes()).unwrap();
+        prop_assert_eq!(tp.get_records(), expected);
+    }
+}
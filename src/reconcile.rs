@@ -0,0 +1,220 @@
+use crate::types::*;
+use crate::client::ClientRecord;
+use crate::status::AccountStatus;
+use crate::processor::TransactionProcessorErr;
+
+use std::collections::{BTreeMap,HashSet};
+use std::fmt;
+use std::io;
+
+use csv::{ReaderBuilder,Trim};
+
+/// Balances differing by less than half of the last written decimal place
+/// always match
+pub const ROUNDING: f64 = 0.00005;
+
+/// A difference between computed and expected account states
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// A balance differs by more than the tolerance
+    Balance {
+        client: ClientID,
+        field: &'static str,
+        actual: f64,
+        expected: f64
+    },
+    /// The lock state differs
+    Locked {
+        client: ClientID,
+        actual: bool,
+        expected: bool
+    },
+    /// The account status differs
+    Status {
+        client: ClientID,
+        actual: AccountStatus,
+        expected: AccountStatus
+    },
+    /// A client was computed but isn't expected
+    Unexpected(ClientID),
+    /// A client is expected but wasn't computed
+    Missing(ClientID)
+}
+
+impl Mismatch {
+    /// Get the ID of the Client the mismatch is for
+    pub fn get_client_id(&self) -> ClientID {
+        use Mismatch::*;
+        match self {
+            Balance { client, .. } | Locked { client, .. } | Status { client, .. } => *client,
+            Unexpected(client) | Missing(client) => *client
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Mismatch::*;
+        match self {
+            Balance { client, field, actual, expected } => {
+                write!(f, "client {}: {} {:.4}, expected {:.4} ({:+.4})",
+                       client, field, actual, expected, actual - expected)
+            },
+            Locked { client, actual, expected } => {
+                write!(f, "client {}: locked {}, expected {}", client, actual, expected)
+            },
+            Status { client, actual, expected } => {
+                write!(f, "client {}: status {}, expected {}", client, actual, expected)
+            },
+            Unexpected(client) => write!(f, "client {}: not in expected balances", client),
+            Missing(client) => write!(f, "client {}: missing from computed balances", client)
+        }
+    }
+}
+
+/// Read a list of Client account states from CSV in the format written by
+/// `write_csv_to_stream`
+///
+/// Note: Each client may only appear once
+pub fn read_records<R>(reader: R) -> Result<Vec<ClientRecord>,TransactionProcessorErr>
+        where R: io::Read {
+    use TransactionProcessorErr::*;
+
+    let mut csv_reader = ReaderBuilder::new()
+        .trim(Trim::All)    // allow leading/trailing whitespace
+        .from_reader(reader);
+    let records: Vec<ClientRecord> = csv_reader.deserialize()
+        .collect::<Result<_,_>>().map_err(CSVError)?;
+
+    let mut seen = HashSet::new();
+    if let Some(record) = records.iter().find(|r| !seen.insert(r.client)) {
+        return Err(InputError(format!("duplicate client {}", record.client)));
+    }
+
+    Ok(records)
+}
+
+/// Compare computed account states against expected ones
+///
+/// Balances match if they differ by no more than the tolerance. Mismatches
+/// are in order of Client ID
+///
+/// Note: Only the balances, lock state and status are compared. The status is
+/// only compared if both records have one
+pub fn reconcile(actual: &[ClientRecord], expected: &[ClientRecord], tolerance: f64)
+        -> Vec<Mismatch> {
+    let expected: BTreeMap<ClientID,&ClientRecord> = expected.iter()
+        .map(|r| (r.client, r))
+        .collect();
+    let actual: BTreeMap<ClientID,&ClientRecord> = actual.iter()
+        .map(|r| (r.client, r))
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for (id, a) in &actual {
+        let e = match expected.get(id) {
+            Some(e) => e,
+            None => {
+                mismatches.push(Mismatch::Unexpected(*id));
+                continue;
+            }
+        };
+
+        for (field, actual, expected) in [("available", a.available, e.available),
+                                          ("held", a.held, e.held),
+                                          ("total", a.total, e.total)] {
+            if (actual - expected).abs() > tolerance + ROUNDING {
+                mismatches.push(Mismatch::Balance { client: *id, field, actual, expected });
+            }
+        }
+        if a.locked != e.locked {
+            mismatches.push(Mismatch::Locked {
+                client: *id,
+                actual: a.locked,
+                expected: e.locked
+            });
+        }
+        if let (Some(actual), Some(expected)) = (a.status, e.status) {
+            if actual != expected {
+                mismatches.push(Mismatch::Status { client: *id, actual, expected });
+            }
+        }
+    }
+    for id in expected.keys().filter(|id| !actual.contains_key(id)) {
+        mismatches.push(Mismatch::Missing(*id));
+    }
+    mismatches.sort_by_key(|m| m.get_client_id());
+
+    mismatches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reconcile_records() {
+        let actual = "client,available,held,total,locked\n\
+                      1,10.0,0.0,10.0,false\n\
+                      2,5.0,1.0,6.0,false\n\
+                      3,1.0,0.0,1.0,true\n\
+                      4,0.0,0.0,0.0,false";
+        let expected = "client,available,held,total,locked,status\n\
+                        1,10.00001,0.0,10.00001,false,active\n\
+                        2,5.5,1.0,6.5,false,active\n\
+                        3,1.0,0.0,1.0,false,active\n\
+                        5,1.0,0.0,1.0,false,active";
+        let actual = read_records(actual.as_bytes()).unwrap();
+        let expected = read_records(expected.as_bytes()).unwrap();
+
+        let mismatches = reconcile(&actual, &expected, 0.0);
+        assert_eq!(mismatches, vec![
+            Mismatch::Balance { client: 2, field: "available", actual: 5.0, expected: 5.5 },
+            Mismatch::Balance { client: 2, field: "total", actual: 6.0, expected: 6.5 },
+            Mismatch::Locked { client: 3, actual: true, expected: false },
+            Mismatch::Unexpected(4),
+            Mismatch::Missing(5)
+        ]);
+        assert_eq!(mismatches[0].to_string(),
+                   "client 2: available 5.0000, expected 5.5000 (-0.5000)");
+
+        let mismatches = reconcile(&actual, &expected, 0.5);
+        assert_eq!(mismatches.len(), 3);
+        assert!(reconcile(&actual, &actual, 0.0).is_empty());
+    }
+
+    #[test]
+    fn reconcile_status() {
+        let actual = "client,available,held,total,locked,status\n\
+                      1,10.0,0.0,10.0,false,frozen\n\
+                      2,5.0,0.0,5.0,false,active";
+        let expected = "client,available,held,total,locked,status\n\
+                        1,10.0,0.0,10.0,false,active\n\
+                        2,5.0,0.0,5.0,false,active";
+        let legacy = "client,available,held,total,locked\n\
+                      1,10.0,0.0,10.0,false\n\
+                      2,5.0,0.0,5.0,false";
+        let actual = read_records(actual.as_bytes()).unwrap();
+        let expected = read_records(expected.as_bytes()).unwrap();
+        let legacy = read_records(legacy.as_bytes()).unwrap();
+
+        let mismatches = reconcile(&actual, &expected, 0.0);
+        assert_eq!(mismatches, vec![Mismatch::Status {
+            client: 1,
+            actual: AccountStatus::Frozen,
+            expected: AccountStatus::Active
+        }]);
+        assert_eq!(mismatches[0].to_string(), "client 1: status frozen, expected active");
+        assert!(reconcile(&actual, &legacy, 0.0).is_empty());
+    }
+
+    #[test]
+    fn read_duplicate_client() {
+        let records = "client,available,held,total,locked\n\
+                       1,10.0,0.0,10.0,false\n\
+                       2,5.0,0.0,5.0,false\n\
+                       1,2.0,0.0,2.0,false";
+        let err = read_records(records.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid input: duplicate client 1");
+    }
+}
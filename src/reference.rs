@@ -0,0 +1,194 @@
+use crate::types::*;
+use crate::client::ClientRecord;
+use crate::status::AccountStatus;
+use crate::processor::TransactionProcessorErr;
+use crate::transaction::TransactionType;
+
+use std::collections::{BTreeMap,HashMap};
+use std::io;
+
+/// A stored deposit or withdrawal
+#[derive(Debug, Clone, Copy)]
+struct Stored {
+    client: ClientID,
+    amount: f64,
+    shortfall: f64,
+    disputed: bool,
+    charged_back: bool
+}
+
+/// An account in the reference model
+#[derive(Debug, Clone, Copy, Default)]
+struct Account {
+    available: f64,
+    held: f64,
+    total: f64,
+    receivable: f64,
+    locked: bool
+}
+
+impl Account {
+    fn apply(&mut self, available: f64, held: f64) {
+        self.available += available;
+        self.held += held;
+        self.total += available + held;
+    }
+}
+
+/// A minimal model of the processor with the default config, written
+/// separately from it to check its results
+///
+/// Only handles deposits, withdrawals and whole disputes, resolves and
+/// chargebacks without timestamps. Every account is locked on its first
+/// chargeback and a locked account rejects everything after it
+#[derive(Debug, Default)]
+pub struct ReferenceModel {
+    accounts: BTreeMap<ClientID,Account>,
+    stored: HashMap<TransactionID,Stored>
+}
+
+impl ReferenceModel {
+    /// Create an empty model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a transaction to the model
+    ///
+    /// Returns whether the transaction was applied
+    pub fn apply(&mut self, typ: TransactionType, client: ClientID, tx: TransactionID,
+                 amount: Option<f64>) -> bool {
+        use TransactionType::*;
+
+        let account = self.accounts.entry(client).or_default();
+        if account.locked {
+            return false;
+        }
+
+        match (typ, amount) {
+            (Deposit | Withdrawal, Some(amount)) => {
+                if (amount <= 0.0) || self.stored.contains_key(&tx) {
+                    return false;
+                }
+                if typ == Deposit {
+                    account.apply(amount, 0.0);
+                } else if account.available >= amount {
+                    account.apply(-amount, 0.0);
+                } else {
+                    return false;
+                }
+                self.stored.insert(tx, Stored {
+                    client,
+                    amount,
+                    shortfall: 0.0,
+                    disputed: false,
+                    charged_back: false
+                });
+                true
+            },
+            (Dispute | Resolve | Chargeback, None) => {
+                let stored = match self.stored.get_mut(&tx) {
+                    Some(stored) if stored.client == client => stored,
+                    _ => return false
+                };
+                match typ {
+                    Dispute if !stored.disputed && !stored.charged_back => {
+                        stored.shortfall = (stored.amount - account.available.max(0.0)).max(0.0);
+                        account.receivable += stored.shortfall;
+                        account.apply(-stored.amount, stored.amount);
+                        stored.disputed = true;
+                    },
+                    Resolve if stored.disputed && (account.held >= stored.amount) => {
+                        account.apply(stored.amount, -stored.amount);
+                        account.receivable -= stored.shortfall;
+                        stored.disputed = false;
+                    },
+                    Chargeback if stored.disputed && (account.held >= stored.amount) => {
+                        account.apply(0.0, -stored.amount);
+                        account.locked = true;
+                        stored.disputed = false;
+                        stored.charged_back = true;
+                    },
+                    _ => return false
+                }
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Get the account state of every Client, in order of their ID, in the
+    /// same format the processor writes by default
+    ///
+    /// Note: The model has no credit so credit_used is always 0
+    pub fn get_records(&self) -> Vec<ClientRecord> {
+        self.accounts.iter()
+            .map(|(id, a)| ClientRecord {
+                client: *id,
+                available: a.available,
+                held: a.held,
+                total: a.total,
+                locked: a.locked,
+                status: Some(if a.locked { AccountStatus::Locked } else { AccountStatus::Active }),
+                receivable: Some(a.receivable),
+                credit_used: Some(0.0)
+            })
+            .collect()
+    }
+
+    /// Write out the account state of every Client as CSV in the same format
+    /// the processor writes by default
+    pub fn write_csv_to_stream<W>(&self, writer: W) -> Result<(),TransactionProcessorErr>
+            where W: io::Write {
+        use TransactionProcessorErr::*;
+
+        let mut csv_writer = csv::Writer::from_writer(writer);
+
+        for r in self.get_records() {
+            csv_writer.serialize(r).map_err(CSVError)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn apply() {
+        let mut m = ReferenceModel::new();
+        assert!(m.apply(Deposit, 1, 1, Some(10.0)));
+        assert!(!m.apply(Deposit, 1, 1, Some(5.0)));
+        assert!(!m.apply(Withdrawal, 1, 2, Some(11.0)));
+        assert!(m.apply(Withdrawal, 1, 3, Some(4.0)));
+        assert!(!m.apply(Dispute, 2, 1, None));
+        assert!(m.apply(Dispute, 1, 1, None));
+        assert!(!m.apply(Dispute, 1, 1, None));
+        assert!(m.apply(Chargeback, 1, 1, None));
+        assert!(!m.apply(Deposit, 1, 4, Some(1.0)));
+
+        let records = m.get_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].available, records[0].held, records[0].total), (-4.0, 0.0, -4.0));
+        assert!(records[0].locked);
+        assert_eq!(records[0].status, Some(AccountStatus::Locked));
+        assert_eq!(records[0].receivable, Some(4.0));
+        assert_eq!(records[1].total, 0.0);
+        assert_eq!(records[1].status, Some(AccountStatus::Active));
+    }
+
+    #[test]
+    fn write_default_columns() {
+        let mut m = ReferenceModel::new();
+        assert!(m.apply(Deposit, 1, 1, Some(2.5)));
+
+        let mut out = Vec::new();
+        m.write_csv_to_stream(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "client,available,held,total,locked,status,receivable,credit_used\n\
+                    1,2.5000,0.0000,2.5000,false,active,0.0000,0.0000\n");
+    }
+}
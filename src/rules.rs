@@ -0,0 +1,247 @@
+use crate::client::Client;
+use crate::history::RejectReason;
+use crate::transaction::{Transaction,TransactionType};
+
+use serde::Deserialize;
+
+/// What a Rule decides about a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Process the transaction
+    Allow,
+    /// Process the transaction without checking any later rules
+    Pass,
+    /// Reject the transaction
+    Reject(RejectReason),
+    /// Process the transaction but flag it in the Client's history
+    Flag,
+    /// Reject the transaction and lock the Client's account
+    Lock(RejectReason)
+}
+
+/// A check run on every transaction, other than admin operations, before it's
+/// processed
+pub trait Rule {
+    /// Get the name the rule flags transactions under
+    fn name(&self) -> &str;
+
+    /// Get the number of a Client's most recent transactions the rule looks at
+    fn window(&self) -> usize {
+        0
+    }
+
+    /// Decide on a transaction given the account and most recent
+    /// transactions of a Client it affects
+    ///
+    /// Note: Transfers and disputes of them are checked for both Clients
+    fn check(&self, trans: &Transaction, client: &Client) -> Verdict;
+}
+
+/// What a built-in rule does with a transaction it matches
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Flag,
+    Reject,
+    Lock
+}
+
+impl Action {
+    /// Get the Verdict for a matched transaction
+    fn verdict(&self, reason: RejectReason) -> Verdict {
+        match self {
+            Action::Flag => Verdict::Flag,
+            Action::Reject => Verdict::Reject(reason),
+            Action::Lock => Verdict::Lock(reason)
+        }
+    }
+}
+
+/// A built-in rule as listed in a config file, e.g.
+/// ```toml
+/// [[rules]]
+/// rule = "large_amount"
+/// threshold = 10000.0
+/// action = "flag"
+/// ```
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum RuleConfig {
+    LargeAmount(LargeAmount),
+    ManyDisputes(ManyDisputes),
+    Structuring(Structuring)
+}
+
+impl RuleConfig {
+    /// Create the Rule
+    pub fn build(&self) -> Box<dyn Rule> {
+        match self {
+            RuleConfig::LargeAmount(r) => Box::new(r.clone()),
+            RuleConfig::ManyDisputes(r) => Box::new(r.clone()),
+            RuleConfig::Structuring(r) => Box::new(r.clone())
+        }
+    }
+}
+
+/// Matches transactions moving at least threshold
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LargeAmount {
+    pub threshold: f64,
+    #[serde(default)]
+    pub action: Action
+}
+
+impl Rule for LargeAmount {
+    fn name(&self) -> &str {
+        "large_amount"
+    }
+
+    fn check(&self, trans: &Transaction, _client: &Client) -> Verdict {
+        use TransactionType::*;
+        match (trans.get_type(), trans.get_amount()) {
+            (Deposit | Withdrawal | Transfer | Authorize, Some(amount))
+                    if amount >= self.threshold => {
+                self.action.verdict(RejectReason::LargeAmount)
+            },
+            _ => Verdict::Allow
+        }
+    }
+}
+
+/// Matches disputes against an account that already had max disputes
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ManyDisputes {
+    pub max: u32,
+    #[serde(default)]
+    pub action: Action
+}
+
+impl Rule for ManyDisputes {
+    fn name(&self) -> &str {
+        "many_disputes"
+    }
+
+    fn check(&self, trans: &Transaction, client: &Client) -> Verdict {
+        if (trans.get_type() == TransactionType::Dispute) &&
+           (client.get_disputes() >= self.max) {
+            self.action.verdict(RejectReason::TooManyDisputes)
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+/// Matches the count-th deposit, withdrawal or transfer within window
+/// transactions that's for just under threshold, i.e. within margin of it
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Structuring {
+    pub threshold: f64,
+    pub margin: f64,
+    pub count: usize,
+    pub window: usize,
+    #[serde(default)]
+    pub action: Action
+}
+
+impl Structuring {
+    /// Check if a transaction is for just under the threshold
+    fn below_threshold(&self, typ: TransactionType, amount: Option<f64>) -> bool {
+        use TransactionType::*;
+        matches!(typ, Deposit | Withdrawal | Transfer) &&
+        amount.is_some_and(|a| (a < self.threshold) &&
+                               (a >= self.threshold - self.margin))
+    }
+}
+
+impl Rule for Structuring {
+    fn name(&self) -> &str {
+        "structuring"
+    }
+
+    fn window(&self) -> usize {
+        self.window
+    }
+
+    fn check(&self, trans: &Transaction, client: &Client) -> Verdict {
+        if (self.window == 0) ||
+           !self.below_threshold(trans.get_type(), trans.get_amount()) {
+            return Verdict::Allow;
+        }
+
+        let count = client.get_recent().last(self.window - 1)
+            .filter(|(typ, amount)| self.below_threshold(*typ, *amount))
+            .count() + 1;
+        if count >= self.count {
+            self.action.verdict(RejectReason::Structuring)
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn large_amount() {
+        let c = Client::new(500);
+        let r = LargeAmount { threshold: 100.0, action: Action::Reject };
+
+        assert_eq!(r.check(&Transaction::new_deposit(500,600,100.0,false), &c),
+                   Verdict::Reject(RejectReason::LargeAmount));
+        assert_eq!(r.check(&Transaction::new_deposit(500,601,99.0,false), &c),
+                   Verdict::Allow);
+        assert_eq!(r.check(&Transaction::new_partial_dispute(500,600,200.0), &c),
+                   Verdict::Allow);
+    }
+
+    #[test]
+    fn many_disputes() {
+        let mut c = Client::new(500);
+        let r = ManyDisputes { max: 1, action: Action::Lock };
+
+        assert_eq!(r.check(&Transaction::new_dispute(500,600), &c), Verdict::Allow);
+        c.add_dispute();
+        assert_eq!(r.check(&Transaction::new_dispute(500,601), &c),
+                   Verdict::Lock(RejectReason::TooManyDisputes));
+        assert_eq!(r.check(&Transaction::new_resolve(500,600), &c), Verdict::Allow);
+    }
+
+    #[test]
+    fn structuring() {
+        let mut c = Client::new(500);
+        let r = Structuring {
+            threshold: 1000.0,
+            margin: 100.0,
+            count: 3,
+            window: 4,
+            action: Action::Flag
+        };
+        let t = Transaction::new_deposit(500,600,950.0,false);
+
+        c.record_recent(Deposit, Some(990.0), None, 3, None);
+        c.record_recent(Deposit, Some(10.0), None, 3, None);
+        assert_eq!(r.check(&t, &c), Verdict::Allow);
+
+        c.record_recent(Withdrawal, Some(900.0), None, 3, None);
+        assert_eq!(r.check(&t, &c), Verdict::Flag);
+        assert_eq!(r.check(&Transaction::new_deposit(500,601,1000.0,false), &c),
+                   Verdict::Allow);
+
+        c.record_recent(Dispute, None, None, 3, None);
+        assert_eq!(r.check(&t, &c), Verdict::Allow);
+    }
+
+    #[test]
+    fn build() {
+        let config = RuleConfig::ManyDisputes(ManyDisputes { max: 2, action: Action::Flag });
+
+        assert_eq!(config.build().name(), "many_disputes");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::client::Client;
+use crate::history::{Event,TEXT_HEADER};
+use crate::processor::TransactionProcessorErr;
+
+use std::io;
+use std::str::FromStr;
+
+/// Formats a statement can be rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementFormat {
+    Csv,
+    Json,
+    Text
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self,Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(StatementFormat::Csv),
+            "json" => Ok(StatementFormat::Json),
+            "text" => Ok(StatementFormat::Text),
+            _ => Err(format!("unknown statement format '{}'", s))
+        }
+    }
+}
+
+/// Write the event history of a list of Clients in the given format
+pub fn write_statement<W>(clients: &[&Client], format: StatementFormat,
+                          mut writer: W) -> Result<(),TransactionProcessorErr>
+        where W: io::Write {
+    use TransactionProcessorErr::*;
+
+    match format {
+        StatementFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            for e in clients.iter().flat_map(|c| c.get_history()) {
+                csv_writer.serialize(e).map_err(CSVError)?;
+            }
+        },
+        StatementFormat::Json => {
+            let events: Vec<&Event> = clients.iter()
+                .flat_map(|c| c.get_history())
+                .collect();
+            serde_json::to_writer_pretty(&mut writer, &events)
+                .map_err(JSONError)?;
+            writeln!(writer).map_err(IOError)?;
+        },
+        StatementFormat::Text => {
+            for c in clients {
+                writeln!(writer, "client {}: available {:.4}, held {:.4}, \
+                                  total {:.4}, receivable {:.4}, status {}",
+                         c.get_client_id(), c.get_available_funds(),
+                         c.get_held_funds(), c.get_total_funds(),
+                         c.get_receivable(), c.get_status()).map_err(IOError)?;
+                writeln!(writer, "{}", TEXT_HEADER).map_err(IOError)?;
+                for e in c.get_history() {
+                    writeln!(writer, "{}", e).map_err(IOError)?;
+                }
+                writeln!(writer).map_err(IOError)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_from_str() {
+        assert_eq!("csv".parse(), Ok(StatementFormat::Csv));
+        assert_eq!("JSON".parse(), Ok(StatementFormat::Json));
+        assert_eq!("text".parse(), Ok(StatementFormat::Text));
+        assert!("xml".parse::<StatementFormat>().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::history::RejectReason;
+use crate::transaction::TransactionType;
+
+use std::fmt;
+
+use serde::{Deserialize,Serialize};
+
+/// Status of a Client's account
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    Frozen,
+    Locked,
+    Closed,
+    Dormant
+}
+
+impl AccountStatus {
+    /// Get the reason a transaction is rejected when the status doesn't
+    /// allow it
+    pub fn reject_reason(&self) -> RejectReason {
+        use AccountStatus::*;
+        match self {
+            Active => RejectReason::NotPermitted,
+            Frozen => RejectReason::AccountFrozen,
+            Locked => RejectReason::AccountLocked,
+            Closed => RejectReason::AccountClosed,
+            Dormant => RejectReason::AccountDormant
+        }
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// Which transaction types are allowed on an account in each status
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusMatrix {
+    active: Vec<TransactionType>,
+    frozen: Vec<TransactionType>,
+    locked: Vec<TransactionType>,
+    closed: Vec<TransactionType>,
+    dormant: Vec<TransactionType>
+}
+
+impl StatusMatrix {
+    /// Check if a transaction type is allowed on an account with a status
+    pub fn allows(&self, status: AccountStatus, typ: TransactionType) -> bool {
+        use AccountStatus::*;
+        let allowed = match status {
+            Active => &self.active,
+            Frozen => &self.frozen,
+            Locked => &self.locked,
+            Closed => &self.closed,
+            Dormant => &self.dormant
+        };
+
+        allowed.contains(&typ)
+    }
+
+    /// Allow or forbid a transaction type on an account with a status
+    #[allow(dead_code)]
+    pub fn set_allowed(&mut self, status: AccountStatus, typ: TransactionType,
+                       allowed: bool) {
+        use AccountStatus::*;
+        let types = match status {
+            Active => &mut self.active,
+            Frozen => &mut self.frozen,
+            Locked => &mut self.locked,
+            Closed => &mut self.closed,
+            Dormant => &mut self.dormant
+        };
+
+        types.retain(|t| *t != typ);
+        if allowed {
+            types.push(typ);
+        }
+    }
+}
+
+impl Default for StatusMatrix {
+    /// Active accounts allow everything, frozen and dormant accounts block
+    /// anything that moves funds out and locked or closed accounts only allow
+    /// admin operations
+    fn default() -> Self {
+        use TransactionType::*;
+        let admin = vec![Unlock, Freeze, Close, Adjust, Limit];
+        let all: Vec<TransactionType> = vec![
+            Deposit, Withdrawal, Transfer, Dispute, Resolve, Chargeback,
+            Authorize, Capture, Void
+        ].into_iter().chain(admin.iter().copied()).collect();
+
+        let inbound: Vec<TransactionType> = all.iter().copied()
+            .filter(|t| !matches!(t, Withdrawal | Transfer | Authorize | Capture))
+            .collect();
+
+        Self {
+            frozen: inbound.clone(),
+            dormant: inbound,
+            active: all,
+            locked: admin.clone(),
+            closed: admin
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn default_matrix() {
+        let m = StatusMatrix::default();
+
+        assert!(m.allows(AccountStatus::Active, Withdrawal));
+        assert!(!m.allows(AccountStatus::Frozen, Withdrawal));
+        assert!(m.allows(AccountStatus::Frozen, Deposit));
+        assert!(!m.allows(AccountStatus::Frozen, Transfer));
+        assert!(!m.allows(AccountStatus::Frozen, Authorize));
+        assert!(!m.allows(AccountStatus::Frozen, Capture));
+        assert!(m.allows(AccountStatus::Frozen, Void));
+        assert!(!m.allows(AccountStatus::Locked, Deposit));
+        assert!(!m.allows(AccountStatus::Locked, Resolve));
+        assert!(m.allows(AccountStatus::Locked, Unlock));
+        assert!(!m.allows(AccountStatus::Closed, Deposit));
+        assert!(m.allows(AccountStatus::Dormant, Deposit));
+        assert!(!m.allows(AccountStatus::Dormant, Transfer));
+    }
+
+    #[test]
+    fn set_allowed() {
+        let mut m = StatusMatrix::default();
+
+        m.set_allowed(AccountStatus::Locked, Resolve, true);
+        m.set_allowed(AccountStatus::Active, Withdrawal, false);
+        assert!(m.allows(AccountStatus::Locked, Resolve));
+        assert!(!m.allows(AccountStatus::Active, Withdrawal));
+    }
+
+    #[test]
+    fn reject_reason() {
+        assert_eq!(AccountStatus::Locked.reject_reason(), RejectReason::AccountLocked);
+        assert_eq!(AccountStatus::Active.reject_reason(), RejectReason::NotPermitted);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::types::*;
+
+use chrono::{DateTime,SecondsFormat};
+use serde::Deserialize;
+
+/// Parse a timestamp given as RFC 3339 or as seconds since the Unix epoch
+pub fn parse_timestamp(s: &str) -> Result<Timestamp,String> {
+    if let Ok(secs) = s.parse::<f64>() {
+        if secs.is_finite() {
+            return Ok((secs * 1000.0).round() as Timestamp);
+        }
+    }
+
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.timestamp_millis())
+        .map_err(|e| format!("invalid timestamp '{}': {}", s, e))
+}
+
+/// Format a timestamp as RFC 3339 in UTC
+pub fn format_timestamp(ts: Timestamp) -> String {
+    match DateTime::from_timestamp_millis(ts) {
+        Some(t) => t.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        None => ts.to_string()
+    }
+}
+
+/// Deserialize an optional timestamp given as RFC 3339 or as seconds since the
+/// Unix epoch
+pub fn deserialize_timestamp<'de, D>(d: D) -> Result<Option<Timestamp>, D::Error>
+        where D: serde::Deserializer<'de> {
+    match Option::<String>::deserialize(d)? {
+        Some(s) if !s.is_empty() => {
+            parse_timestamp(&s).map(Some).map_err(serde::de::Error::custom)
+        },
+        _ => Ok(None)
+    }
+}
+
+/// Serialize an optional timestamp as RFC 3339 in UTC
+pub fn serialize_timestamp<S>(data: &Option<Timestamp>, s: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer {
+    match data {
+        Some(ts) => s.serialize_str(&format_timestamp(*ts)),
+        None => s.serialize_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_timestamp("1700000000"), Ok(1_700_000_000_000));
+        assert_eq!(parse_timestamp("1700000000.25"), Ok(1_700_000_000_250));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z"), Ok(1_700_000_000_000));
+        assert_eq!(parse_timestamp("2023-11-14T23:13:20.5+01:00"), Ok(1_700_000_000_500));
+        assert!(parse_timestamp("yesterday").is_err());
+        assert!(parse_timestamp("NaN").is_err());
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_timestamp(1_700_000_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_timestamp(1_700_000_000_500), "2023-11-14T22:13:20.500Z");
+    }
+}
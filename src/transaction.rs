@@ -1,9 +1,11 @@
 use crate::types::*;
+use crate::input::Source;
+use crate::time::deserialize_timestamp;
 
-use serde::Deserialize;
+use serde::{Deserialize,Serialize};
 
 /// Struct representing a transaction
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Transaction {
     #[serde(rename = "type")]
     typ: TransactionType,
@@ -11,19 +13,48 @@ pub struct Transaction {
     #[serde(rename = "tx")]
     id: TransactionID,
     amount: Option<f64>,
+    counterparty: Option<ClientID>,
+    reason: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    timestamp: Option<Timestamp>,
     #[serde(skip)]
-    in_dispute: bool
+    in_dispute: bool,
+    #[serde(skip)]
+    disputed: f64,
+    #[serde(skip)]
+    charged_back: f64,
+    #[serde(skip)]
+    shortfall: f64,
+    #[serde(skip)]
+    unheld: f64,
+    #[serde(skip)]
+    disputed_at: Option<Timestamp>,
+    #[serde(skip)]
+    synthetic: bool,
+    #[serde(skip)]
+    authorized: f64,
+    #[serde(skip)]
+    source: Option<Source>
 }
 
 /// Different types of transactions
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
     Withdrawal,
+    Transfer,
     Dispute,
     Resolve,
-    Chargeback
+    Chargeback,
+    Authorize,
+    Capture,
+    Void,
+    Unlock,
+    Freeze,
+    Close,
+    Adjust,
+    Limit
 }
 
 impl Transaction {
@@ -31,77 +62,232 @@ impl Transaction {
     #[allow(dead_code)]
     pub fn new_deposit(client: ClientID, id: TransactionID, amount: f64,
                        in_dispute: bool) -> Self {
-        Self {
-            typ: TransactionType::Deposit,
-            client,
-            id,
-            amount: Some(amount),
-            in_dispute
-        }
+        Self::from_fields(TransactionType::Deposit, client, id, Some(amount), None, None)
+            .with_dispute(in_dispute)
     }
 
     /// Create a new withdrawl transaction from the provided info
     #[allow(dead_code)]
     pub fn new_withdrawl(client: ClientID, id: TransactionID, amount: f64,
                        in_dispute: bool) -> Self {
-        Self {
-            typ: TransactionType::Withdrawal,
-            client,
-            id,
-            amount: Some(amount),
-            in_dispute
-        }
+        Self::from_fields(TransactionType::Withdrawal, client, id, Some(amount), None, None)
+            .with_dispute(in_dispute)
+    }
+
+    /// Create a new transfer transaction from the provided info
+    ///
+    /// Funds are moved from the account of client to the account of
+    /// counterparty
+    #[allow(dead_code)]
+    pub fn new_transfer(client: ClientID, counterparty: ClientID,
+                        id: TransactionID, amount: f64,
+                        in_dispute: bool) -> Self {
+        Self::from_fields(TransactionType::Transfer, client, id, Some(amount),
+                          Some(counterparty), None)
+            .with_dispute(in_dispute)
     }
 
     /// Create a new dispute transaction from the provided info
     #[allow(dead_code)]
     pub fn new_dispute(client: ClientID, id: TransactionID) -> Self {
-        Self {
-            typ: TransactionType::Dispute,
-            client,
-            id,
-            amount: None,
-            in_dispute: false
-        }
+        Self::from_fields(TransactionType::Dispute, client, id, None, None, None)
+    }
+
+    /// Create a new dispute transaction for part of a transaction's amount
+    #[allow(dead_code)]
+    pub fn new_partial_dispute(client: ClientID, id: TransactionID,
+                               amount: f64) -> Self {
+        Self::from_fields(TransactionType::Dispute, client, id, Some(amount), None, None)
     }
 
     /// Create a new resolve transaction from the provided info
     #[allow(dead_code)]
     pub fn new_resolve(client: ClientID, id: TransactionID) -> Self {
-        Self {
-            typ: TransactionType::Resolve,
-            client,
-            id,
-            amount: None,
-            in_dispute: false
-        }
+        Self::from_fields(TransactionType::Resolve, client, id, None, None, None)
     }
 
     /// Create a new chargeback transaction from the provided info
     #[allow(dead_code)]
     pub fn new_chargeback(client: ClientID, id: TransactionID) -> Self {
+        Self::from_fields(TransactionType::Chargeback, client, id, None, None, None)
+    }
+
+    /// Create a new chargeback transaction for part of a transaction's
+    /// disputed amount
+    #[allow(dead_code)]
+    pub fn new_partial_chargeback(client: ClientID, id: TransactionID,
+                                  amount: f64) -> Self {
+        Self::from_fields(TransactionType::Chargeback, client, id, Some(amount), None, None)
+    }
+
+    /// Create a new authorize transaction from the provided info
+    #[allow(dead_code)]
+    pub fn new_authorize(client: ClientID, id: TransactionID, amount: f64) -> Self {
+        Self::from_fields(TransactionType::Authorize, client, id, Some(amount), None, None)
+    }
+
+    /// Create a new capture transaction from the provided info
+    ///
+    /// Note: The full remaining authorized amount is captured if no amount
+    /// is given
+    #[allow(dead_code)]
+    pub fn new_capture(client: ClientID, id: TransactionID,
+                       amount: Option<f64>) -> Self {
+        Self::from_fields(TransactionType::Capture, client, id, amount, None, None)
+    }
+
+    /// Create a new void transaction from the provided info
+    #[allow(dead_code)]
+    pub fn new_void(client: ClientID, id: TransactionID) -> Self {
+        Self::from_fields(TransactionType::Void, client, id, None, None, None)
+    }
+
+    /// Create a new admin transaction from the provided info
+    ///
+    /// Note: Only adjustments and limits have an amount. An adjustment's amount
+    /// is signed and it must have a reason. A limit's amount is the new
+    /// overdraft limit
+    #[allow(dead_code)]
+    pub fn new_admin(typ: TransactionType, client: ClientID, id: TransactionID,
+                     amount: Option<f64>, reason: Option<&str>) -> Self {
+        Self {
+            reason: reason.map(String::from),
+            ..Self::from_fields(typ, client, id, amount, None, None)
+        }
+    }
+
+    /// Create a transaction from the fields of a CSV row without a reason
+    ///
+    /// Every other constructor builds on this one, so it's the only place a
+    /// new field needs a starting value
+    ///
+    /// Note: The transaction isn't validated
+    pub fn from_fields(typ: TransactionType, client: ClientID, id: TransactionID,
+                       amount: Option<f64>, counterparty: Option<ClientID>,
+                       timestamp: Option<Timestamp>) -> Self {
         Self {
-            typ: TransactionType::Chargeback,
+            typ,
             client,
             id,
-            amount: None,
-            in_dispute: false
+            amount,
+            counterparty,
+            reason: None,
+            timestamp,
+            in_dispute: false,
+            disputed: 0.0,
+            charged_back: 0.0,
+            shortfall: 0.0,
+            unheld: 0.0,
+            disputed_at: None,
+            synthetic: false,
+            authorized: 0.0,
+            source: None
         }
     }
 
+    /// Mark the full amount of a new transaction as disputed if in_dispute
+    fn with_dispute(mut self, in_dispute: bool) -> Self {
+        if in_dispute {
+            self.in_dispute = true;
+            self.disputed = self.amount.unwrap_or(0.0);
+        }
+        self
+    }
+
+    /// Validate the fields of a transaction against its type
     pub fn validate(&self) -> bool {
         use TransactionType::*;
+        // only admin transactions can give a reason
+        if self.reason.is_some() && !self.is_admin() {
+            return false;
+        }
+        // amounts must be real numbers
+        if self.amount.is_some_and(|a| !a.is_finite()) {
+            return false;
+        }
+
         match self.typ {
             Deposit | Withdrawal => {
-                return self.amount.is_some();
+                (self.amount.is_some_and(|a| a > 0.0)) &&
+                (self.counterparty.is_none())
+            },
+            Transfer => {
+                (self.amount.is_some_and(|a| a > 0.0)) &&
+                (self.counterparty.is_some()) &&
+                (self.counterparty != Some(self.client))
+            },
+            Resolve | Void => {
+                (self.amount.is_none()) &&
+                (self.counterparty.is_none()) &&
+                (!self.in_dispute)
+            },
+            Dispute | Chargeback | Capture => {
+                (self.amount.is_none_or(|a| a > 0.0)) &&
+                (self.counterparty.is_none()) &&
+                (!self.in_dispute)
+            },
+            Unlock | Freeze | Close => {
+                (self.amount.is_none()) &&
+                (self.counterparty.is_none())
+            },
+            Adjust => {
+                (self.amount.is_some_and(|a| a != 0.0)) &&
+                (self.counterparty.is_none()) &&
+                (self.reason.as_ref().is_some_and(|r| !r.is_empty()))
+            },
+            Limit => {
+                (self.amount.is_some_and(|a| a >= 0.0)) &&
+                (self.counterparty.is_none())
             },
-            Dispute | Resolve | Chargeback => {
-                return (self.amount.is_none()) &&
-                       (!self.in_dispute);
+            Authorize => {
+                (self.amount.is_some_and(|a| a > 0.0)) &&
+                (self.counterparty.is_none()) &&
+                (!self.in_dispute)
             }
         }
     }
 
+    /// Get the time the transaction was made
+    pub fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
+    /// Set the time the transaction was made
+    pub fn set_timestamp(&mut self, timestamp: Option<Timestamp>) {
+        self.timestamp = timestamp;
+    }
+
+    /// Check if the transaction was made by the processor rather than read
+    /// from its input
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic
+    }
+
+    /// Mark the transaction as made by the processor
+    pub fn set_synthetic(&mut self) {
+        self.synthetic = true;
+    }
+
+    /// Get the input file and line the transaction was read from
+    pub fn get_source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Set the input file and line the transaction was read from
+    pub fn set_source(&mut self, source: Option<Source>) {
+        self.source = source;
+    }
+
+    /// Get the time the oldest open dispute on the transaction was opened
+    pub fn get_disputed_at(&self) -> Option<Timestamp> {
+        self.disputed_at
+    }
+
+    /// Set the time the oldest open dispute on the transaction was opened
+    pub fn set_disputed_at(&mut self, timestamp: Option<Timestamp>) {
+        self.disputed_at = timestamp;
+    }
+
     /// Get the type fo the transaction
     pub fn get_type(&self) -> TransactionType {
         self.typ
@@ -124,30 +310,204 @@ impl Transaction {
         self.amount
     }
 
+    /// Get the Client ID receiving the funds of a transfer
+    ///
+    /// Note: Only transfers have a counterparty
+    pub fn get_counterparty(&self) -> Option<ClientID> {
+        self.counterparty
+    }
+
+    /// Get the Client ID on the other side of a transfer from the given Client
+    ///
+    /// Note: Returns None if the transaction isn't a transfer or the given
+    /// Client isn't a party to it
+    pub fn get_other_party(&self, client: ClientID) -> Option<ClientID> {
+        match self.counterparty {
+            Some(counterparty) if client == self.client => Some(counterparty),
+            Some(_) if Some(client) == self.counterparty => Some(self.client),
+            _ => None
+        }
+    }
+
+    /// Get the reason given for an admin transaction
+    #[allow(dead_code)]
+    pub fn get_reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Check if the transaction is an admin operation
+    ///
+    /// Admin operations are only processed when explicitly allowed
+    pub fn is_admin(&self) -> bool {
+        use TransactionType::*;
+        matches!(self.typ, Unlock | Freeze | Close | Adjust | Limit)
+    }
+
+    /// Check if the transaction refers to an earlier transaction by its ID
+    /// rather than having an ID of its own
+    pub fn is_reference(&self) -> bool {
+        use TransactionType::*;
+        matches!(self.typ, Dispute | Resolve | Chargeback | Capture | Void)
+    }
+
+    /// Get the remaining authorized amount of an authorization
+    pub fn get_authorized(&self) -> f64 {
+        self.authorized
+    }
+
+    /// Set the remaining authorized amount of an authorization
+    ///
+    /// Note: Only authorizations can have an authorized amount
+    pub fn set_authorized(&mut self, amount: f64) {
+        if self.typ == TransactionType::Authorize {
+            self.authorized = amount;
+        }
+    }
+
     /// Get the dispute status of the transaction
     pub fn is_disputed(&self) -> bool {
         self.in_dispute
     }
 
+    /// Get the amount of the transaction that's currently disputed
+    pub fn get_disputed_amount(&self) -> f64 {
+        self.disputed
+    }
+
+    /// Get the amount of the transaction that's currently held for disputes
+    /// or authorization
+    pub fn get_held_amount(&self) -> f64 {
+        if self.typ == TransactionType::Authorize {
+            self.authorized
+        } else {
+            self.split_disputed(self.disputed).0
+        }
+    }
+
+    /// Get the Client ID of the account holding funds for the transaction
+    ///
+    /// Note: Funds for a disputed transfer are held by its counterparty
+    pub fn get_holder(&self) -> ClientID {
+        self.counterparty.unwrap_or(self.client)
+    }
+
+    /// Get the amount of the transaction that's been charged back
+    #[allow(dead_code)]
+    pub fn get_charged_back_amount(&self) -> f64 {
+        self.charged_back
+    }
+
+    /// Get the amount of the transaction that's neither disputed nor charged
+    /// back
+    ///
+    /// Note: Only deposits, withdrawals and transfers can be disputed
+    pub fn get_undisputed_amount(&self) -> f64 {
+        if self.is_disputable() {
+            self.amount.unwrap_or(0.0) - self.disputed - self.charged_back
+        } else {
+            0.0
+        }
+    }
+
     /// Mark a transaction as disputed
     ///
-    /// Note: Only deposits and withdrawals can be marked as disputed
+    /// All of the undisputed amount of the transaction becomes disputed
+    ///
+    /// Note: Only deposits, withdrawals and transfers can be marked as disputed
+    #[allow(dead_code)]
     pub fn set_disputed(&mut self) {
-        use TransactionType::*;
-        match self.typ {
-            Deposit | Withdrawal => self.in_dispute = true,
-            _ => ()
-        }
+        self.add_disputed(self.get_undisputed_amount());
     }
 
     /// clear dispute status on a transaction
+    #[allow(dead_code)]
     pub fn clear_disputed(&mut self) {
-        use TransactionType::*;
-        match self.typ {
-            Deposit | Withdrawal => self.in_dispute = false,
-            _ => ()
+        self.remove_disputed(self.disputed);
+    }
+
+    /// Mark part of a transaction as disputed
+    ///
+    /// Note: Only deposits, withdrawals and transfers can be marked as disputed
+    pub fn add_disputed(&mut self, amount: f64) {
+        if self.is_disputable() {
+            self.disputed += amount;
+            self.in_dispute = true;
+        }
+    }
+
+    /// Get the disputed amount of the transaction that exceeded the available
+    /// funds when it was disputed
+    #[allow(dead_code)]
+    pub fn get_shortfall(&self) -> f64 {
+        self.shortfall
+    }
+
+    /// Record part of the disputed amount as a shortfall
+    ///
+    /// Note: A shortfall that was not held is left out of the held amount
+    pub fn add_shortfall(&mut self, amount: f64, held: bool) {
+        if self.is_disputable() {
+            self.shortfall += amount;
+            if !held {
+                self.unheld += amount;
+            }
         }
     }
+
+    /// Split part of the disputed amount into its held and shortfall amounts
+    ///
+    /// Both are in proportion to the part of the disputed amount given
+    pub fn split_disputed(&self, amount: f64) -> (f64,f64) {
+        if self.disputed > 0.0 {
+            let fraction = amount / self.disputed;
+            ((self.disputed - self.unheld) * fraction, self.shortfall * fraction)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    /// Clear the dispute on part of a transaction
+    ///
+    /// Any shortfall is cleared in proportion to the amount
+    pub fn remove_disputed(&mut self, amount: f64) {
+        if self.is_disputable() {
+            if self.disputed > 0.0 {
+                let fraction = amount / self.disputed;
+                self.shortfall -= self.shortfall * fraction;
+                self.unheld -= self.unheld * fraction;
+            }
+            self.disputed -= amount;
+            self.in_dispute = self.disputed > 0.0;
+            if !self.in_dispute {
+                self.disputed_at = None;
+            }
+        }
+    }
+
+    /// Charge back part of the disputed amount of a transaction
+    pub fn add_charged_back(&mut self, amount: f64) {
+        if self.is_disputable() {
+            self.remove_disputed(amount);
+            self.charged_back += amount;
+        }
+    }
+
+    /// Get the part of the disputed amount given that wasn't held
+    ///
+    /// Note: This is in proportion to the part of the disputed amount given
+    pub fn get_unheld_amount(&self, amount: f64) -> f64 {
+        if self.disputed > 0.0 {
+            self.unheld * (amount / self.disputed)
+        } else {
+            0.0
+        }
+    }
+
+    /// Check if the transaction type can be disputed
+    fn is_disputable(&self) -> bool {
+        use TransactionType::*;
+        matches!(self.typ, Deposit | Withdrawal | Transfer)
+    }
 }
 
 #[cfg(test)]
@@ -162,7 +522,7 @@ mod test {
         assert_eq!(t.client,500);
         assert_eq!(t.id,600);
         assert_eq!(t.amount,Some(100.0));
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(t.validate());
     }
 
@@ -174,7 +534,7 @@ mod test {
         assert_eq!(t.client,500);
         assert_eq!(t.id,600);
         assert_eq!(t.amount,Some(100.0));
-        assert_eq!(t.in_dispute,true);
+        assert!(t.in_dispute);
         assert!(t.validate());
     }
 
@@ -186,7 +546,7 @@ mod test {
         assert_eq!(t.client,500);
         assert_eq!(t.id,600);
         assert_eq!(t.amount,None);
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(t.validate());
     }
 
@@ -198,7 +558,7 @@ mod test {
         assert_eq!(t.client,500);
         assert_eq!(t.id,600);
         assert_eq!(t.amount,None);
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(t.validate());
     }
 
@@ -210,36 +570,61 @@ mod test {
         assert_eq!(t.client,500);
         assert_eq!(t.id,600);
         assert_eq!(t.amount,None);
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(t.validate());
     }
 
     #[test]
     fn fail_validate() {
-        let t1 = Transaction {
-            typ: TransactionType::Deposit,
-            client: 500,
-            id: 600,
-            amount: None,
-            in_dispute: false
-        };
-        let t2 = Transaction {
-            typ: TransactionType::Dispute,
-            client: 500,
-            id: 600,
-            amount: Some(100.0),
-            in_dispute: true
-        };
-
-        assert_eq!(t1.validate(),false);
-        assert_eq!(t2.validate(),false);
+        let t1 = Transaction::from_fields(TransactionType::Deposit,500,600,None,None,None);
+        let t2 = Transaction::from_fields(TransactionType::Dispute,500,600,Some(100.0),None,None)
+            .with_dispute(true);
+
+        assert!(!t1.validate());
+        assert!(!t2.validate());
+    }
+
+    #[test]
+    fn new_transfer() {
+        let t = Transaction::new_transfer(500,501,600,100.0,false);
+
+        assert_eq!(t.typ,TransactionType::Transfer);
+        assert_eq!(t.client,500);
+        assert_eq!(t.counterparty,Some(501));
+        assert_eq!(t.id,600);
+        assert_eq!(t.amount,Some(100.0));
+        assert!(!t.in_dispute);
+        assert!(t.validate());
+    }
+
+    #[test]
+    fn fail_validate_transfer() {
+        let t1 = Transaction::new_transfer(500,500,600,100.0,false);
+        let t2 = Transaction::from_fields(TransactionType::Transfer,500,600,Some(100.0),None,None);
+        let mut t3 = Transaction::new_deposit(500,600,100.0,false);
+        t3.counterparty = Some(501);
+
+        assert!(!t1.validate());
+        assert!(!t2.validate());
+        assert!(!t3.validate());
+    }
+
+    #[test]
+    fn get_other_party() {
+        let t1 = Transaction::new_transfer(500,501,600,100.0,false);
+        let t2 = Transaction::new_deposit(500,600,100.0,false);
+
+        assert_eq!(t1.get_other_party(500),Some(501));
+        assert_eq!(t1.get_other_party(501),Some(500));
+        assert_eq!(t1.get_other_party(502),None);
+        assert_eq!(t2.get_other_party(500),None);
     }
 
     #[test]
     fn set_disputed() {
         let mut t = Transaction::new_deposit(500,600,100.0,false);
 
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(!t.is_disputed());
         t.set_disputed();
         assert!(t.in_dispute);
@@ -253,7 +638,7 @@ mod test {
         assert!(t.in_dispute);
         assert!(t.is_disputed());
         t.clear_disputed();
-        assert_eq!(t.in_dispute,false);
+        assert!(!t.in_dispute);
         assert!(!t.is_disputed());
     }
 
@@ -267,4 +652,170 @@ mod test {
         assert_eq!(t.get_amount(), t.amount);
         assert_eq!(t.is_disputed(), t.in_dispute);
     }
+
+    #[test]
+    fn new_authorize() {
+        let t = Transaction::new_authorize(500,600,100.0);
+
+        assert_eq!(t.typ,TransactionType::Authorize);
+        assert_eq!(t.amount,Some(100.0));
+        assert_eq!(t.authorized,0.0);
+        assert!(t.validate());
+    }
+
+    #[test]
+    fn new_capture() {
+        let t1 = Transaction::new_capture(500,600,None);
+        let t2 = Transaction::new_capture(500,600,Some(10.0));
+        let t3 = Transaction::new_capture(500,600,Some(-10.0));
+
+        assert_eq!(t1.typ,TransactionType::Capture);
+        assert!(t1.validate());
+        assert!(t2.validate());
+        assert!(!t3.validate());
+    }
+
+    #[test]
+    fn new_void() {
+        let t = Transaction::new_void(500,600);
+
+        assert_eq!(t.typ,TransactionType::Void);
+        assert_eq!(t.amount,None);
+        assert!(t.validate());
+    }
+
+    #[test]
+    fn set_authorized() {
+        let mut t1 = Transaction::new_authorize(500,600,100.0);
+        let mut t2 = Transaction::new_deposit(500,600,100.0,false);
+
+        t1.set_authorized(40.0);
+        t2.set_authorized(40.0);
+        assert_eq!(t1.get_authorized(),40.0);
+        assert_eq!(t2.get_authorized(),0.0);
+    }
+
+    #[test]
+    fn partial_dispute() {
+        let mut t = Transaction::new_deposit(500,600,100.0,false);
+
+        t.add_disputed(30.0);
+        assert!(t.is_disputed());
+        assert_eq!(t.get_disputed_amount(),30.0);
+        assert_eq!(t.get_undisputed_amount(),70.0);
+
+        t.add_charged_back(10.0);
+        assert!(t.is_disputed());
+        assert_eq!(t.get_disputed_amount(),20.0);
+        assert_eq!(t.get_charged_back_amount(),10.0);
+        assert_eq!(t.get_undisputed_amount(),70.0);
+
+        t.remove_disputed(20.0);
+        assert!(!t.is_disputed());
+        assert_eq!(t.get_undisputed_amount(),90.0);
+    }
+
+    #[test]
+    fn partial_dispute_validate() {
+        let mut t1 = Transaction::new_partial_dispute(500,600,10.0);
+        let mut t2 = Transaction::new_partial_chargeback(500,600,10.0);
+        let mut t3 = Transaction::new_resolve(500,600);
+
+        t3.amount = Some(10.0);
+        assert!(t1.validate());
+        assert!(t2.validate());
+        assert!(!t3.validate());
+
+        t1.amount = Some(0.0);
+        t2.amount = Some(-10.0);
+        assert!(!t1.validate());
+        assert!(!t2.validate());
+    }
+
+    #[test]
+    fn new_admin() {
+        use TransactionType::*;
+        let t1 = Transaction::new_admin(Unlock,500,600,None,None);
+        let t2 = Transaction::new_admin(Adjust,500,601,Some(-10.0),Some("fee refund"));
+
+        assert_eq!(t1.typ,Unlock);
+        assert!(t1.is_admin());
+        assert!(t1.validate());
+        assert_eq!(t2.get_reason(),Some("fee refund"));
+        assert!(t2.is_admin());
+        assert!(t2.validate());
+        assert!(!Transaction::new_deposit(500,600,100.0,false).is_admin());
+    }
+
+    #[test]
+    fn fail_validate_admin() {
+        use TransactionType::*;
+        let t1 = Transaction::new_admin(Adjust,500,600,Some(10.0),None);
+        let t2 = Transaction::new_admin(Adjust,500,600,Some(10.0),Some(""));
+        let t3 = Transaction::new_admin(Adjust,500,600,None,Some("fix"));
+        let t4 = Transaction::new_admin(Freeze,500,600,Some(10.0),None);
+        let mut t5 = Transaction::new_deposit(500,600,100.0,false);
+        t5.reason = Some(String::from("fix"));
+
+        assert!(!t1.validate());
+        assert!(!t2.validate());
+        assert!(!t3.validate());
+        assert!(!t4.validate());
+        assert!(!t5.validate());
+    }
+
+    #[test]
+    fn split_disputed() {
+        let mut t = Transaction::new_deposit(500,600,100.0,false);
+
+        t.add_disputed(100.0);
+        t.add_shortfall(60.0,false);
+        assert_eq!(t.split_disputed(100.0),(40.0,60.0));
+        assert_eq!(t.split_disputed(50.0),(20.0,30.0));
+        assert_eq!(t.get_unheld_amount(50.0),30.0);
+
+        t.remove_disputed(50.0);
+        assert_eq!(t.get_shortfall(),30.0);
+        assert_eq!(t.split_disputed(50.0),(20.0,30.0));
+        assert_eq!(t.get_unheld_amount(50.0),30.0);
+
+        t.add_disputed(10.0);
+        t.add_shortfall(10.0,true);
+        assert_eq!(t.get_unheld_amount(60.0),30.0);
+    }
+
+    #[test]
+    fn validate_limit() {
+        use TransactionType::*;
+        let t1 = Transaction::new_admin(Limit,500,600,Some(100.0),None);
+        let t2 = Transaction::new_admin(Limit,500,601,Some(0.0),Some("closed credit line"));
+        let t3 = Transaction::new_admin(Limit,500,602,Some(-1.0),None);
+        let t4 = Transaction::new_admin(Limit,500,603,None,None);
+
+        assert!(t1.validate());
+        assert!(t1.is_admin());
+        assert!(t2.validate());
+        assert!(!t3.validate());
+        assert!(!t4.validate());
+    }
+
+    #[test]
+    fn validate_amounts() {
+        use TransactionType::*;
+        let t1 = Transaction::new_deposit(500,600,-1.0,false);
+        let t2 = Transaction::new_withdrawl(500,601,0.0,false);
+        let t3 = Transaction::new_transfer(500,501,602,-5.0,false);
+        let t4 = Transaction::new_authorize(500,603,-2.0);
+        let t5 = Transaction::new_deposit(500,604,f64::INFINITY,false);
+        let t6 = Transaction::new_admin(Adjust,500,605,Some(f64::NAN),Some("fix"));
+        let t7 = Transaction::new_admin(Deposit,500,606,None,None);
+
+        assert!(!t1.validate());
+        assert!(!t2.validate());
+        assert!(!t3.validate());
+        assert!(!t4.validate());
+        assert!(!t5.validate());
+        assert!(!t6.validate());
+        assert!(!t7.validate());
+    }
 }
@@ -1,2 +1,5 @@
 pub type ClientID = u16;
 pub type TransactionID = u32;
+
+/// Milliseconds since the Unix epoch
+pub type Timestamp = i64;
@@ -0,0 +1,145 @@
+use crate::types::*;
+use crate::history::{RecentTransactions,RejectReason};
+use crate::transaction::TransactionType;
+
+use serde::Deserialize;
+
+/// Caps on a Client's withdrawals over a rolling window of their most recent
+/// transactions, or of time
+///
+/// Transfers out and authorizations move funds out too, so count and are
+/// checked as withdrawals. A capture isn't, as its authorization already was.
+/// A withdrawal with a timestamp is checked against the time window if one
+/// is set, and any other against the window of transactions
+///
+/// Note: A window of 0 transactions and no time window disables the caps
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VelocityLimits {
+    /// Number of transactions, including the one being checked, in the window
+    pub window: usize,
+    /// Seconds before the transaction being checked in the window
+    pub window_secs: Option<f64>,
+    /// Maximum number of withdrawals, transfers and authorizations in the window
+    pub max_count: Option<u32>,
+    /// Maximum total amount withdrawn in the window
+    pub max_total: Option<f64>,
+    /// Freeze the account when a withdrawal breaches a cap
+    pub freeze: bool
+}
+
+impl VelocityLimits {
+    /// Get the time window in milliseconds
+    pub fn get_time_window(&self) -> Option<Timestamp> {
+        self.window_secs.map(|w| (w * 1000.0).round() as Timestamp)
+    }
+
+    /// Check if a withdrawal of an amount made at a time stays within the
+    /// caps given a Client's recent transactions
+    pub fn check(&self, recent: &RecentTransactions, amount: f64,
+                 timestamp: Option<Timestamp>) -> Result<(),RejectReason> {
+        let (count, total) = match (self.get_time_window(), timestamp) {
+            (Some(window), Some(ts)) => withdrawals(recent.since(ts.saturating_sub(window))),
+            _ if self.window > 0 => withdrawals(recent.last(self.window - 1)),
+            _ => return Ok(())
+        };
+        if self.max_count.is_some_and(|m| count + 1 > m) {
+            Err(RejectReason::VelocityCount)
+        } else if self.max_total.is_some_and(|m| total + amount > m) {
+            Err(RejectReason::VelocityTotal)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Check if a transaction type moves funds out of its Client's account, so
+/// counts as a withdrawal for the caps
+pub fn is_outbound(typ: TransactionType) -> bool {
+    use TransactionType::*;
+    matches!(typ, Withdrawal | Transfer | Authorize)
+}
+
+/// Count and total the withdrawals in a list of transactions
+fn withdrawals<I>(recent: I) -> (u32,f64)
+        where I: Iterator<Item = (TransactionType,Option<f64>)> {
+    recent.filter(|(typ, _)| is_outbound(*typ))
+        .fold((0, 0.0), |(count, total), (_, amount)| {
+            (count + 1, total + amount.unwrap_or(0.0))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::TransactionType::*;
+
+    #[test]
+    fn check() {
+        let mut w = RecentTransactions::default();
+        let limits = VelocityLimits {
+            window: 3,
+            window_secs: None,
+            max_count: Some(2),
+            max_total: Some(100.0),
+            freeze: false
+        };
+
+        assert_eq!(limits.check(&w, 100.0, None), Ok(()));
+        assert_eq!(limits.check(&w, 100.5, None), Err(RejectReason::VelocityTotal));
+
+        w.record(Withdrawal, Some(10.0), None, 3, None);
+        w.record(Withdrawal, Some(10.0), None, 3, None);
+        assert_eq!(limits.check(&w, 10.0, None), Err(RejectReason::VelocityCount));
+
+        w.record(Dispute, None, None, 3, None);
+        assert_eq!(limits.check(&w, 80.0, None), Ok(()));
+        assert_eq!(limits.check(&w, 90.5, None), Err(RejectReason::VelocityTotal));
+
+        w.record(Transfer, Some(20.0), None, 3, None);
+        assert_eq!(limits.check(&w, 80.5, None), Err(RejectReason::VelocityTotal));
+        // the authorization counts but its capture doesn't
+        w.record(Authorize, Some(20.0), None, 3, None);
+        w.record(Capture, Some(20.0), None, 3, None);
+        assert_eq!(limits.check(&w, 10.0, None), Ok(()));
+        assert_eq!(limits.check(&w, 80.5, None), Err(RejectReason::VelocityTotal));
+    }
+
+    #[test]
+    fn check_disabled() {
+        let w = RecentTransactions::default();
+        let limits = VelocityLimits {
+            window: 0,
+            window_secs: None,
+            max_count: Some(0),
+            max_total: Some(0.0),
+            freeze: true
+        };
+
+        assert_eq!(limits.check(&w, 1.0, None), Ok(()));
+    }
+
+    #[test]
+    fn check_time_window() {
+        let mut w = RecentTransactions::default();
+        let limits = VelocityLimits {
+            window: 2,
+            window_secs: Some(60.0),
+            max_count: Some(2),
+            max_total: None,
+            freeze: false
+        };
+        let span = limits.get_time_window();
+
+        w.record(Withdrawal, Some(10.0), Some(0), 2, span);
+        w.record(Deposit, Some(10.0), Some(30_000), 2, span);
+        w.record(Withdrawal, Some(10.0), Some(50_000), 2, span);
+        // both earlier withdrawals are inside the window, though not in the
+        //  last 2 transactions
+        assert_eq!(limits.check(&w, 10.0, Some(59_000)), Err(RejectReason::VelocityCount));
+        // the first has left the window
+        assert_eq!(limits.check(&w, 10.0, Some(60_000)), Ok(()));
+        // without a timestamp the window of transactions is used
+        assert_eq!(limits.check(&w, 10.0, None), Ok(()));
+    }
+}